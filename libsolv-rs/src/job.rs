@@ -0,0 +1,149 @@
+use bitflags::bitflags;
+
+use crate::pool::Pool;
+use crate::queue::Queue;
+use crate::selection::Selection;
+use crate::solvable::Solvable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobAction {
+    Install = libsolv_sys::SOLVER_INSTALL as isize,
+    Erase = libsolv_sys::SOLVER_ERASE as isize,
+    Update = libsolv_sys::SOLVER_UPDATE as isize,
+    Lock = libsolv_sys::SOLVER_LOCK as isize,
+    DistUpgrade = libsolv_sys::SOLVER_DISTUPGRADE as isize,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct JobFlags: u32 {
+    const WEAK = libsolv_sys::SOLVER_WEAK;
+    const ESSENTIAL = libsolv_sys::SOLVER_ESSENTIAL;
+    const CLEANDEPS = libsolv_sys::SOLVER_CLEANDEPS;
+    }
+}
+
+/// Job for the solver.  `how` is the action and the kind of
+/// selection, and `what` the selected id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Job {
+    pub how: libsolv_sys::Id,
+    pub what: libsolv_sys::Id,
+}
+
+impl Job {
+    pub fn new(how: u32, what: libsolv_sys::Id) -> Self {
+        Job {
+            how: how as libsolv_sys::Id,
+            what,
+        }
+    }
+
+    pub fn install() -> JobBuilder {
+        JobBuilder::new(JobAction::Install)
+    }
+
+    pub fn erase() -> JobBuilder {
+        JobBuilder::new(JobAction::Erase)
+    }
+
+    pub fn update() -> JobBuilder {
+        JobBuilder::new(JobAction::Update)
+    }
+
+    pub fn lock() -> JobBuilder {
+        JobBuilder::new(JobAction::Lock)
+    }
+
+    pub fn distupgrade() -> JobBuilder {
+        JobBuilder::new(JobAction::DistUpgrade)
+    }
+
+    /// Install a solvable with this name
+    pub fn install_name(pool: &mut Pool, name: &str) -> Self {
+        Job::install().name(pool, name)
+    }
+
+    /// Install the pattern (`pattern:name`)
+    pub fn install_pattern(pool: &mut Pool, name: &str) -> Self {
+        Job::install_name(pool, &format!("pattern:{}", name))
+    }
+
+    /// Install the product (`product:name`)
+    pub fn install_product(pool: &mut Pool, name: &str) -> Self {
+        Job::install_name(pool, &format!("product:{}", name))
+    }
+}
+
+/// Builder for jobs, like `Job::erase().clean_deps().name(&mut pool, "vim")`
+#[derive(Debug, Clone, Copy)]
+pub struct JobBuilder {
+    action: JobAction,
+    flags: JobFlags,
+}
+
+impl JobBuilder {
+    pub fn new(action: JobAction) -> Self {
+        JobBuilder {
+            action,
+            flags: JobFlags::empty(),
+        }
+    }
+
+    /// The job can be ignored if it cannot be solved
+    pub fn weak(mut self) -> Self {
+        self.flags |= JobFlags::WEAK;
+        self
+    }
+
+    pub fn essential(mut self) -> Self {
+        self.flags |= JobFlags::ESSENTIAL;
+        self
+    }
+
+    /// Also erase the dependencies that are not needed anymore
+    pub fn clean_deps(mut self) -> Self {
+        self.flags |= JobFlags::CLEANDEPS;
+        self
+    }
+
+    pub(crate) fn select(&self, select: u32, what: libsolv_sys::Id) -> Job {
+        Job::new(self.action as u32 | self.flags.bits() | select, what)
+    }
+
+    pub fn solvable(&self, solvable: &Solvable) -> Job {
+        self.select(libsolv_sys::SOLVER_SOLVABLE, solvable.id())
+    }
+
+    /// All the solvables, like in `Job::distupgrade().all()`
+    pub fn all(&self) -> Job {
+        self.select(libsolv_sys::SOLVER_SOLVABLE_ALL, 0)
+    }
+
+    /// Solvables with this name
+    pub fn name(&self, pool: &mut Pool, name: &str) -> Job {
+        self.select(libsolv_sys::SOLVER_SOLVABLE_NAME, pool.str2id(name, true))
+    }
+
+    /// Solvables providing this name.  For dependencies with a
+    /// version use `Selection::provides`
+    pub fn provides(&self, pool: &mut Pool, name: &str) -> Job {
+        self.select(
+            libsolv_sys::SOLVER_SOLVABLE_PROVIDES,
+            pool.str2id(name, true),
+        )
+    }
+
+    pub fn selection(&self, selection: &Selection) -> Vec<Job> {
+        selection.jobs(*self)
+    }
+}
+
+/// Job queue as expected by `solver_solve`
+pub(crate) fn queue(jobs: &[Job]) -> Queue {
+    let mut queue = Queue::with_capacity(jobs.len() * 2);
+    for job in jobs {
+        queue.push2(job.how, job.what);
+    }
+    queue
+}
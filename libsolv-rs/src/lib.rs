@@ -1,8 +1,10 @@
 pub mod chksumtype;
+pub mod job;
 pub mod map;
 pub mod pool;
 pub mod queue;
 pub mod repo;
+pub mod selection;
 pub mod solvable;
 pub mod solver;
 pub mod transaction;
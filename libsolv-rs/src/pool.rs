@@ -1,19 +1,16 @@
+use std::cmp;
 use std::cmp::Eq;
-use std::env;
 use std::ffi;
-use std::fs;
-use std::mem;
 use std::ptr;
 use std::str;
 
 use bitflags::bitflags;
 
-use crate::queue::Queue;
-use crate::repo::Repo;
-use crate::solvable::Solvable;
-use crate::solver::Solver;
-use crate::transaction::TransactionMode;
-use crate::transaction::TransactionType;
+use crate::repo::{Repo, RepoMut, Repos};
+use crate::solvable::{Solvable, Solvables};
+
+// Bit of the ids that are relations (`MAKERELDEP` in libsolv)
+const RELDEP_MASK: u32 = 0x8000_0000;
 
 // TODO: Find better names
 pub enum DebugLevel {
@@ -84,13 +81,110 @@ impl Pool {
         unsafe { libsolv_sys::pool_set_flag(self.pool, flag as i32, status as i32) != 0 }
     }
 
-    pub fn get_flag(&mut self, flag: PoolFlag) -> bool {
+    pub fn get_flag(&self, flag: PoolFlag) -> bool {
         unsafe { libsolv_sys::pool_get_flag(self.pool, flag as i32) != 0 }
     }
 
     // TODO: pool_debug (requires c_variadic)
 
-    pub fn solvable2str(&mut self, solvable: &mut Solvable) -> String {
+    /// Set the architecture of the system, used to decide which
+    /// solvables are installable
+    pub fn set_arch(&mut self, arch: &str) {
+        let arch_c = ffi::CString::new(arch).unwrap();
+        unsafe {
+            libsolv_sys::pool_setarch(self.pool, arch_c.as_ptr());
+        }
+    }
+
+    /// Repo with id `repoid`, to load or free it
+    pub fn repo_mut(&mut self, repoid: libsolv_sys::Id) -> Option<RepoMut<'_>> {
+        unsafe {
+            if repoid <= 0 || repoid >= (*self.pool).nrepos {
+                return None;
+            }
+            let repo = *(*self.pool).repos.offset(repoid as isize);
+            if repo.is_null() {
+                None
+            } else {
+                Some(RepoMut { repo, pool: self })
+            }
+        }
+    }
+
+    pub fn installed(&self) -> Option<Repo<'_>> {
+        unsafe {
+            let repo = (*self.pool).installed;
+            if repo.is_null() {
+                None
+            } else {
+                Some(Repo { repo, pool: self })
+            }
+        }
+    }
+
+    /// No repo is the installed system anymore.  The repo stays in
+    /// the pool
+    pub fn unset_installed(&mut self) {
+        unsafe {
+            libsolv_sys::pool_set_installed(self.pool, ptr::null_mut());
+        }
+    }
+
+    /// Last error reported by libsolv
+    pub fn errstr(&self) -> String {
+        unsafe {
+            ffi::CStr::from_ptr(libsolv_sys::pool_errstr(self.pool))
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// Must be called after loading all the repos and before solving
+    pub fn add_file_provides(&mut self) {
+        unsafe {
+            libsolv_sys::pool_addfileprovides(self.pool);
+        }
+    }
+
+    /// Must be called after loading all the repos and before solving
+    pub fn create_whatprovides(&mut self) {
+        unsafe {
+            libsolv_sys::pool_createwhatprovides(self.pool);
+        }
+    }
+
+    pub fn set_rootdir(&mut self, rootdir: &str) {
+        let rootdir_c = ffi::CString::new(rootdir).unwrap();
+        unsafe {
+            libsolv_sys::pool_set_rootdir(self.pool, rootdir_c.as_ptr());
+        }
+    }
+
+    pub fn get_rootdir(&self) -> Option<String> {
+        unsafe {
+            let rootdir = libsolv_sys::pool_get_rootdir(self.pool);
+            if rootdir.is_null() {
+                None
+            } else {
+                Some(ffi::CStr::from_ptr(rootdir).to_string_lossy().into_owned())
+            }
+        }
+    }
+
+    /// Path of `path` inside the root directory of the pool
+    pub fn prepend_rootdir(&self, path: &str) -> String {
+        let path_c = ffi::CString::new(path).unwrap();
+        unsafe {
+            ffi::CStr::from_ptr(libsolv_sys::pool_prepend_rootdir_tmp(
+                self.pool,
+                path_c.as_ptr(),
+            ))
+            .to_string_lossy()
+            .into_owned()
+        }
+    }
+
+    pub fn solvable2str(&self, solvable: &Solvable) -> String {
         unsafe {
             ffi::CStr::from_ptr(libsolv_sys::pool_solvable2str(self.pool, solvable.solvable))
                 .to_string_lossy()
@@ -100,6 +194,7 @@ impl Pool {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct RelFlags: u32 {
     const GT = libsolv_sys::REL_GT;
     const EQ = libsolv_sys::REL_EQ;
@@ -124,6 +219,8 @@ bitflags! {
 
 // From poolid. Maybe can be replaces with some enum
 impl Pool {
+    /// Id of the string.  With `create` the string is added to the
+    /// string space of the pool when it is not there
     pub fn str2id(&mut self, string: &str, create: bool) -> libsolv_sys::Id {
         let string_c = ffi::CString::new(string).unwrap();
         unsafe { libsolv_sys::pool_str2id(self.pool, string_c.as_ptr(), create as i32) }
@@ -136,10 +233,12 @@ impl Pool {
         flags: RelFlags,
         create: bool,
     ) -> libsolv_sys::Id {
-        unsafe { libsolv_sys::pool_rel2id(self.pool, name, evr, flags.bits as i32, create as i32) }
+        unsafe {
+            libsolv_sys::pool_rel2id(self.pool, name, evr, flags.bits() as i32, create as i32)
+        }
     }
 
-    pub fn id2str(&mut self, id: libsolv_sys::Id) -> String {
+    pub fn id2str(&self, id: libsolv_sys::Id) -> String {
         unsafe {
             ffi::CStr::from_ptr(libsolv_sys::pool_id2str(self.pool, id))
                 .to_string_lossy()
@@ -147,7 +246,7 @@ impl Pool {
         }
     }
 
-    pub fn id2rel(&mut self, id: libsolv_sys::Id) -> String {
+    pub fn id2rel(&self, id: libsolv_sys::Id) -> String {
         unsafe {
             ffi::CStr::from_ptr(libsolv_sys::pool_id2rel(self.pool, id))
                 .to_string_lossy()
@@ -155,7 +254,7 @@ impl Pool {
         }
     }
 
-    pub fn id2evr(&mut self, id: libsolv_sys::Id) -> String {
+    pub fn id2evr(&self, id: libsolv_sys::Id) -> String {
         unsafe {
             ffi::CStr::from_ptr(libsolv_sys::pool_id2evr(self.pool, id))
                 .to_string_lossy()
@@ -163,7 +262,7 @@ impl Pool {
         }
     }
 
-    pub fn dep2str(&mut self, id: libsolv_sys::Id) -> String {
+    pub fn dep2str(&self, id: libsolv_sys::Id) -> String {
         unsafe {
             ffi::CStr::from_ptr(libsolv_sys::pool_dep2str(self.pool, id))
                 .to_string_lossy()
@@ -195,37 +294,58 @@ impl Pool {
         }
     }
 
-    // TODO remove
-    pub fn nrepos(&self) -> i32 {
-        unsafe { (*self.pool).nrepos }
+    /// Repos of the pool
+    pub fn repos(&self) -> Repos<'_> {
+        Repos {
+            pool: self,
+            next: 1,
+        }
     }
 
-    // TODO remove
-    pub fn repo(&self, repoid: i32) -> Option<Repo> {
-        let repo;
-        unsafe {
-            repo = (*self.pool).repos.offset(repoid as isize);
-        }
+    /// Solvables of all the repos of the pool
+    pub fn solvables(&self) -> Solvables<'_> {
+        Solvables::all(self)
+    }
 
-        if repo.is_null() {
-            None
-        } else {
-            Some(Repo {
-                repo: unsafe { *repo },
-            })
-        }
+    /// Solvable with id `p`, if it is in use
+    pub fn solvable(&self, p: libsolv_sys::Id) -> Option<Solvable<'_>> {
+        Solvable::from_id(self, p)
+    }
+
+    /// The whatprovides index is created
+    /// (`Pool::create_whatprovides`)
+    pub fn has_whatprovides(&self) -> bool {
+        unsafe { !(*self.pool).whatprovides.is_null() }
     }
-    // TODO remove
-    pub fn solvable(&self, solvableid: i32) -> Option<Solvable> {
-        let solvable;
+
+    /// `id` is a string or a relation of the pool
+    pub fn is_dep(&self, id: libsolv_sys::Id) -> bool {
         unsafe {
-            solvable = (*self.pool).solvables.offset(solvableid as isize);
+            if id as u32 & RELDEP_MASK == 0 {
+                id > 0 && id < (*self.pool).ss.nstrings
+            } else {
+                let rel = (id as u32 ^ RELDEP_MASK) as libsolv_sys::Id;
+                rel > 0 && rel < (*self.pool).nrels
+            }
         }
+    }
+}
+
+pub enum EvrCmpMode {
+    Compare = libsolv_sys::EVRCMP_COMPARE as isize,
+    MatchRelease = libsolv_sys::EVRCMP_MATCH_RELEASE as isize,
+    Match = libsolv_sys::EVRCMP_MATCH as isize,
+    CompareEvOnly = libsolv_sys::EVRCMP_COMPARE_EVONLY as isize,
+}
 
-        if solvable.is_null() {
-            None
-        } else {
-            Some(Solvable { solvable })
+// From evr
+impl Pool {
+    pub fn evrcmp_str(&self, evr1: &str, evr2: &str, mode: EvrCmpMode) -> cmp::Ordering {
+        let evr1_c = ffi::CString::new(evr1).unwrap();
+        let evr2_c = ffi::CString::new(evr2).unwrap();
+        unsafe {
+            libsolv_sys::pool_evrcmp_str(self.pool, evr1_c.as_ptr(), evr2_c.as_ptr(), mode as i32)
+                .cmp(&0)
         }
     }
 }
@@ -239,6 +359,7 @@ impl Drop for Pool {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TestCaseResult: u32 {
     const TRANSACTION = libsolv_sys::TESTCASE_RESULT_TRANSACTION;
     const PROBLEMS = libsolv_sys::TESTCASE_RESULT_PROBLEMS;
@@ -257,105 +378,29 @@ bitflags! {
 }
 
 // TODO - Where to put this definition?
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Package {
     pub name: String,
     pub version: String,
     pub arch: String,
+    pub vendor: String,
+    /// Name of the repository of the solvable.  For a solv cache it is
+    /// the zypper alias
+    pub repo: String,
 }
 
 impl Package {
-    pub fn full_name(&self) -> String {
-        format!("{}-{}.{}", self.name, self.version, self.arch)
-    }
-}
-
-// From testcase
-impl Pool {
-    // TODO full refactor (Vec<String>)
-    pub fn testsolv(&mut self, testcase: &str) -> Vec<Package> {
-        let mut packages = Vec::new();
-        let mut testcase_filename = env::temp_dir();
-        testcase_filename.push("testcase.solv");
-        // Make sure that we end with a new line
-        fs::write(&testcase_filename, &format!("{testcase}\n")).expect("Error writing test case");
-
-        let mut job = Queue::new();
-        let mut result = ptr::null_mut();
-        let resultflags = TestCaseResult::empty();
-        let testcase_filename_c = ffi::CString::new(testcase_filename.to_str().unwrap()).unwrap();
-        let mode = ffi::CStr::from_bytes_with_nul(b"r\0").unwrap();
-        let name = ffi::CStr::from_bytes_with_nul(b"testcase.solv\0").unwrap();
-        let mut solver;
-        unsafe {
-            // TODO: this can fail
-            let testcase_file = libc::fopen(testcase_filename_c.as_ptr(), mode.as_ptr());
-            let solv = libsolv_sys::testcase_read(
-                self.pool,
-                mem::transmute(testcase_file),
-                name.as_ptr(),
-                &mut job.queue,
-                &mut result,
-                &mut (resultflags.bits as i32),
-            );
-            if solv.is_null() {
-                return packages;
-            }
-            solver = Solver { solver: solv };
-            libc::fclose(testcase_file);
-        }
-
-        let problem_cnt = solver.solve(job);
-        if problem_cnt > 0 {
-            for problem in 1..=problem_cnt {
-                solver.print_problem_info(problem);
-            }
-            panic!("Found problems with the test case");
-        }
-
-        let mut transaction = solver.transaction();
-
-        let mut classes = Queue::new();
-        let mut pkgs = Queue::new();
-        let mode = TransactionMode::SHOW_OBSOLETES | TransactionMode::OBSOLETE_IS_UPGRADE;
-        transaction.classify(mode, &mut classes);
-
-        for class_i in (0..classes.queue.count).step_by(4) {
-            let class;
-            // let count;
-            let from;
-            let to;
-            unsafe {
-                class = *classes.queue.elements.offset(class_i as isize);
-                // count = *classes.queue.elements.offset(class_i as isize + 1);
-                from = *classes.queue.elements.offset(class_i as isize + 2);
-                to = *classes.queue.elements.offset(class_i as isize + 3);
-            }
-            match class.try_into() {
-                Ok(TransactionType::Install) => {}
-                Ok(_) => panic!("Except only installations"),
-                Err(_) => panic!("Not recognized transaction type"),
-            }
-
-            transaction.classify_pkgs(mode, class.try_into().unwrap(), from, to, &mut pkgs);
-            for j in 0..pkgs.queue.count {
-                let p;
-                unsafe {
-                    p = *pkgs.queue.elements.offset(j as isize);
-                }
-                let mut s = self.solvable(p).expect("Missing solvable");
-                // TODO how to re-use all the stuff except this part,
-                // so we can return different structures
-                // packages.push(self.solvable2str(&mut s));
-                packages.push(Package {
-                    name: s.name(),
-                    version: s.evr(),
-                    arch: s.arch(),
-                });
-            }
+    pub fn from_solvable(solvable: &Solvable) -> Package {
+        Package {
+            name: solvable.name(),
+            version: solvable.evr(),
+            arch: solvable.arch(),
+            vendor: solvable.vendor(),
+            repo: solvable.repo().name(),
         }
+    }
 
-        fs::remove_file(testcase_filename).expect("Error removing the test case");
-        packages
+    pub fn full_name(&self) -> String {
+        format!("{}-{}.{}", self.name, self.version, self.arch)
     }
 }
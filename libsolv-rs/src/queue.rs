@@ -39,6 +39,26 @@ impl Queue {
         Queue { queue }
     }
 
+    pub fn len(&self) -> usize {
+        self.queue.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.count == 0
+    }
+
+    pub fn to_vec(&self) -> Vec<libsolv_sys::Id> {
+        if self.queue.elements.is_null() {
+            return Vec::new();
+        }
+        unsafe { std::slice::from_raw_parts(self.queue.elements, self.len()).to_vec() }
+    }
+
+    /// Append two elements at the end
+    pub fn push2(&mut self, element1: libsolv_sys::Id, element2: libsolv_sys::Id) {
+        self.insert2(self.len(), element1, element2);
+    }
+
     // TODO: Replace Id element with the proper enum type
     pub fn insert(&mut self, index: usize, element: libsolv_sys::Id) {
         unsafe {
@@ -1,11 +1,14 @@
 use std::ffi;
 use std::mem;
+use std::ptr;
 
 use bitflags::bitflags;
 
 use crate::pool::Pool;
+use crate::solvable::Solvables;
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct RepoFlags: u32 {
     const REUSE_REPODATA = libsolv_sys::REPO_REUSE_REPODATA;
     const NO_INITIALIZE = libsolv_sys::REPO_NO_INTERNALIZE;
@@ -17,9 +20,44 @@ bitflags! {
     }
 }
 
+/// Repo stored in the pool.  Repos are owned by the pool, and are
+/// freed with it or with `RepoMut::free`.  This handle can only read
+/// the repo, to add or remove solvables use `RepoMut`
 #[derive(Debug)]
-pub struct Repo {
+pub struct Repo<'pool> {
     pub(crate) repo: *mut libsolv_sys::Repo,
+    pub(crate) pool: &'pool Pool,
+}
+
+/// Repo that is being loaded.  Adding or removing solvables can move
+/// the solvables of the pool, so this handle borrows the pool
+/// exclusively, and no `Repo` or `Solvable` can be alive meanwhile
+///
+/// ```compile_fail
+/// use libsolv_rs::pool::Pool;
+/// use libsolv_rs::repo::RepoMut;
+///
+/// let mut pool = Pool::new();
+/// let p = RepoMut::new(&mut pool, "first").add_solvable();
+/// let solvable = pool.solvable(p).unwrap();
+/// RepoMut::new(&mut pool, "second").add_solvable_block(1000);
+/// solvable.name();
+/// ```
+///
+/// ```compile_fail
+/// use libsolv_rs::pool::Pool;
+/// use libsolv_rs::repo::RepoMut;
+///
+/// let mut pool = Pool::new();
+/// let id = RepoMut::new(&mut pool, "test").id();
+/// let repo = pool.repos().next().unwrap();
+/// pool.repo_mut(id).unwrap().free(true);
+/// repo.name();
+/// ```
+#[derive(Debug)]
+pub struct RepoMut<'pool> {
+    pub(crate) repo: *mut libsolv_sys::Repo,
+    pub(crate) pool: &'pool mut Pool,
 }
 
 #[derive(Debug)]
@@ -27,12 +65,101 @@ pub struct RepoSideData {
     pub(crate) _repo_sidedata: *mut ::std::os::raw::c_void,
 }
 
-impl Repo {
-    pub fn new(pool: &mut Pool, name: &str) -> Self {
+impl<'pool> Repo<'pool> {
+    pub fn pool(&self) -> &'pool Pool {
+        self.pool
+    }
+
+    pub fn id(&self) -> libsolv_sys::Id {
+        unsafe { (*self.repo).repoid }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe {
+            let name = (*self.repo).name;
+            if name.is_null() {
+                String::new()
+            } else {
+                ffi::CStr::from_ptr(name).to_string_lossy().into_owned()
+            }
+        }
+    }
+
+    pub fn priority(&self) -> i32 {
+        unsafe { (*self.repo).priority }
+    }
+
+    pub fn disabled(&self) -> bool {
+        unsafe { (*self.repo).disabled != 0 }
+    }
+
+    /// Solvables of the repo
+    pub fn solvables(&self) -> Solvables<'pool> {
+        unsafe { Solvables::new(self.pool, self.repo, (*self.repo).start, (*self.repo).end) }
+    }
+
+    pub fn nsolvables(&self) -> i32 {
+        unsafe { (*self.repo).nsolvables }
+    }
+}
+
+impl<'pool> RepoMut<'pool> {
+    pub fn new(pool: &'pool mut Pool, name: &str) -> Self {
         let name_c = ffi::CString::new(name).unwrap();
         unsafe {
             let repo = libsolv_sys::repo_create(pool.pool, name_c.as_ptr());
-            Repo { repo }
+            RepoMut { repo, pool }
+        }
+    }
+
+    /// Read only view of the repo
+    pub fn repo(&self) -> Repo<'_> {
+        Repo {
+            repo: self.repo,
+            pool: self.pool,
+        }
+    }
+
+    pub fn pool(&self) -> &Pool {
+        self.pool
+    }
+
+    /// Remove the repo and its solvables from the pool
+    pub fn free(self, reuse_ids: bool) {
+        unsafe { libsolv_sys::repo_free(self.repo, reuse_ids as i32) }
+    }
+
+    pub fn id(&self) -> libsolv_sys::Id {
+        self.repo().id()
+    }
+
+    pub fn name(&self) -> String {
+        self.repo().name()
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.repo().priority()
+    }
+
+    pub fn set_priority(&mut self, priority: i32) {
+        unsafe {
+            (*self.repo).priority = priority;
+        }
+    }
+
+    /// The solvables of a disabled repo are left out of the
+    /// whatprovides index, so the solver does not see them.  The index
+    /// must be created again after the change
+    pub fn set_disabled(&mut self, disabled: bool) {
+        unsafe {
+            (*self.repo).disabled = disabled as i32;
+        }
+    }
+
+    /// Mark the repo as the installed system
+    pub fn set_installed(&mut self) {
+        unsafe {
+            libsolv_sys::pool_set_installed(self.pool.pool, self.repo);
         }
     }
 
@@ -44,20 +171,22 @@ impl Repo {
         todo!()
     }
 
+    /// Add an empty solvable, returning its id
     pub fn add_solvable(&mut self) -> libsolv_sys::Id {
-        todo!()
+        unsafe { libsolv_sys::repo_add_solvable(self.repo) }
     }
 
-    pub fn add_solvable_block(&mut self, _count: i32) -> libsolv_sys::Id {
-        todo!()
+    /// Add `count` empty solvables, returning the id of the first one
+    pub fn add_solvable_block(&mut self, count: i32) -> libsolv_sys::Id {
+        unsafe { libsolv_sys::repo_add_solvable_block(self.repo, count) }
     }
 
-    pub fn free_solvable(&mut self, _p: libsolv_sys::Id, _reuse_ids: bool) {
-        todo!()
+    pub fn free_solvable(&mut self, p: libsolv_sys::Id, reuse_ids: bool) {
+        unsafe { libsolv_sys::repo_free_solvable(self.repo, p, reuse_ids as i32) }
     }
 
-    pub fn free_solvable_block(&mut self, _start: libsolv_sys::Id, _count: i32, _reuse_ids: bool) {
-        todo!()
+    pub fn free_solvable_block(&mut self, start: libsolv_sys::Id, count: i32, reuse_ids: bool) {
+        unsafe { libsolv_sys::repo_free_solvable_block(self.repo, start, count, reuse_ids as i32) }
     }
 
     pub fn sidedata_create(&mut self, _size: usize) -> RepoSideData {
@@ -74,7 +203,11 @@ impl Repo {
         todo!()
     }
 
-    pub fn add_sovable_block_before(&mut self, _count: i32, _repo: &mut Repo) -> libsolv_sys::Id {
+    pub fn add_sovable_block_before(
+        &mut self,
+        _count: i32,
+        _repo: &mut RepoMut<'pool>,
+    ) -> libsolv_sys::Id {
         todo!()
     }
 
@@ -103,45 +236,72 @@ impl Repo {
         todo!()
     }
 
+    /// Load a solv file.  With `RepoFlags::USE_ROOTDIR` the file name
+    /// is relative to the root directory of the pool.  Returns -1 if
+    /// the file cannot be opened, like when it cannot be read
     pub fn add_solv(&mut self, solv_filename: &str, flags: RepoFlags) -> i32 {
-        let solv_filename_c = ffi::CString::new(solv_filename).unwrap();
+        let mut solv_filename_c = ffi::CString::new(solv_filename).unwrap();
+        if flags.contains(RepoFlags::USE_ROOTDIR) {
+            unsafe {
+                let rooted =
+                    libsolv_sys::pool_prepend_rootdir_tmp(self.pool.pool, solv_filename_c.as_ptr());
+                solv_filename_c = ffi::CStr::from_ptr(rooted).to_owned();
+            }
+        }
         let mode = ffi::CStr::from_bytes_with_nul(b"r\0").unwrap();
         unsafe {
-            // TODO: this can fail
             let solv_file = libc::fopen(solv_filename_c.as_ptr(), mode.as_ptr());
-            let repo =
-                libsolv_sys::repo_add_solv(self.repo, mem::transmute(solv_file), flags.bits as i32);
+            if solv_file.is_null() {
+                return -1;
+            }
+            let repo = libsolv_sys::repo_add_solv(
+                self.repo,
+                mem::transmute(solv_file),
+                flags.bits() as i32,
+            );
             libc::fclose(solv_file);
             repo
         }
     }
 
-    // TODO remove
-    pub fn start(&self) -> i32 {
-        unsafe { (*self.repo).start }
-    }
-    // TODO remove
-    pub fn end(&self) -> i32 {
-        unsafe { (*self.repo).end }
+    /// Load the installed packages from the rpm database.  With
+    /// `RepoFlags::USE_ROOTDIR` the database is read from the root
+    /// directory of the pool
+    pub fn add_rpmdb(&mut self, flags: RepoFlags) -> i32 {
+        unsafe { libsolv_sys::repo_add_rpmdb(self.repo, ptr::null_mut(), flags.bits() as i32) }
     }
-    // TODO remove
-    pub fn nsolvables(&self) -> i32 {
-        unsafe { (*self.repo).nsolvables }
-    }
-    // TODO remove
-    pub fn pool(&self) -> Pool {
-        unsafe {
-            Pool {
-                pool: (*self.repo).pool,
-            }
-        }
+
+    /// Create `pattern:` and `product:` solvables for the packages
+    /// that provide `pattern()` or `product()`
+    pub fn add_autopattern(&mut self) -> i32 {
+        unsafe { libsolv_sys::repo_add_autopattern(self.repo, 0) }
     }
 }
 
-impl Drop for Repo {
-    fn drop(&mut self) {
+/// Iterator over the repos of the pool.  Freed repos are skipped
+#[derive(Debug)]
+pub struct Repos<'pool> {
+    pub(crate) pool: &'pool Pool,
+    pub(crate) next: libsolv_sys::Id,
+}
+
+impl<'pool> Iterator for Repos<'pool> {
+    type Item = Repo<'pool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
         unsafe {
-            libsolv_sys::repo_free(self.repo, 1);
+            // Repo ids start at 1
+            while self.next < (*self.pool.pool).nrepos {
+                let repo = *(*self.pool.pool).repos.offset(self.next as isize);
+                self.next += 1;
+                if !repo.is_null() {
+                    return Some(Repo {
+                        repo,
+                        pool: self.pool,
+                    });
+                }
+            }
         }
+        None
     }
 }
@@ -0,0 +1,180 @@
+use std::ffi;
+
+use bitflags::bitflags;
+
+use crate::job::{Job, JobBuilder};
+use crate::pool::Pool;
+use crate::queue::Queue;
+use crate::solvable::Solvable;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SelectionFlags: u32 {
+    const NAME = libsolv_sys::SELECTION_NAME;
+    const PROVIDES = libsolv_sys::SELECTION_PROVIDES;
+    const FILELIST = libsolv_sys::SELECTION_FILELIST;
+    const CANON = libsolv_sys::SELECTION_CANON;
+    const DOTARCH = libsolv_sys::SELECTION_DOTARCH;
+    const REL = libsolv_sys::SELECTION_REL;
+    const INSTALLED_ONLY = libsolv_sys::SELECTION_INSTALLED_ONLY;
+    const GLOB = libsolv_sys::SELECTION_GLOB;
+    const FLAT = libsolv_sys::SELECTION_FLAT;
+    const NOCASE = libsolv_sys::SELECTION_NOCASE;
+    const SOURCE_ONLY = libsolv_sys::SELECTION_SOURCE_ONLY;
+    const WITH_SOURCE = libsolv_sys::SELECTION_WITH_SOURCE;
+    const SKIP_KIND = libsolv_sys::SELECTION_SKIP_KIND;
+    const MATCH_DEPSTR = libsolv_sys::SELECTION_MATCH_DEPSTR;
+    const WITH_DISABLED = libsolv_sys::SELECTION_WITH_DISABLED;
+    const WITH_BADARCH = libsolv_sys::SELECTION_WITH_BADARCH;
+    }
+}
+
+/// Set of solvables, stored as `how`/`what` pairs like a job queue.
+/// The selections are made with the whatprovides index of the pool
+/// (`Pool::create_whatprovides`), and there is none before it is
+/// created.  Like a `Job`, it only holds ids, and it must be used with
+/// the pool where it was made.  The operations that add a set of
+/// solvables to the whatprovides data of the pool take it mutably
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub(crate) queue: Queue,
+}
+
+impl Selection {
+    /// Empty selection
+    pub fn new() -> Self {
+        Selection {
+            queue: Queue::new(),
+        }
+    }
+
+    /// Solvables matching `name` as told by the flags.  `None` if the
+    /// whatprovides index is not created
+    pub fn make(pool: &Pool, name: &str, flags: SelectionFlags) -> Option<Self> {
+        if !pool.has_whatprovides() {
+            return None;
+        }
+        let mut selection = Selection::new();
+        let name_c = ffi::CString::new(name).unwrap();
+        unsafe {
+            libsolv_sys::selection_make(
+                pool.pool,
+                &mut selection.queue.queue,
+                name_c.as_ptr(),
+                flags.bits() as i32,
+            );
+        }
+        Some(selection)
+    }
+
+    /// Solvables with this name, like `kernel-default`
+    pub fn name(pool: &Pool, name: &str) -> Option<Self> {
+        Selection::make(pool, name, SelectionFlags::NAME)
+    }
+
+    /// Solvables providing the dependency, like `kernel` or
+    /// `kernel >= 6`
+    pub fn provides(pool: &Pool, dep: &str) -> Option<Self> {
+        Selection::make(pool, dep, SelectionFlags::PROVIDES | SelectionFlags::REL)
+    }
+
+    /// Solvables with a name matching the glob, like `kernel-*`
+    pub fn glob(pool: &Pool, glob: &str) -> Option<Self> {
+        Selection::make(pool, glob, SelectionFlags::NAME | SelectionFlags::GLOB)
+    }
+
+    /// Solvable with the canonical name, like
+    /// `kernel-default-6.4.0-1.1.x86_64`
+    pub fn canon(pool: &Pool, nevra: &str) -> Option<Self> {
+        Selection::make(pool, nevra, SelectionFlags::CANON)
+    }
+
+    /// Solvables providing the dependency id, like a conflict of a
+    /// solvable.  `None` if the whatprovides index is not created or
+    /// `dep` is not an id of the pool
+    pub fn whatprovides(pool: &Pool, dep: libsolv_sys::Id) -> Option<Self> {
+        if !pool.has_whatprovides() || !pool.is_dep(dep) {
+            return None;
+        }
+        let mut selection = Selection::new();
+        selection.queue.push2(
+            libsolv_sys::SOLVER_SOLVABLE_PROVIDES as libsolv_sys::Id,
+            dep,
+        );
+        Some(selection)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Keep only the solvables that are also in `other`
+    pub fn filter(&mut self, pool: &mut Pool, other: &Selection) {
+        let mut other = other.queue.clone();
+        unsafe {
+            libsolv_sys::selection_filter(pool.pool, &mut self.queue.queue, &mut other.queue);
+        }
+    }
+
+    pub fn add(&mut self, pool: &Pool, other: &Selection) {
+        let mut other = other.queue.clone();
+        unsafe {
+            libsolv_sys::selection_add(pool.pool, &mut self.queue.queue, &mut other.queue);
+        }
+    }
+
+    pub fn subtract(&mut self, pool: &mut Pool, other: &Selection) {
+        let mut other = other.queue.clone();
+        unsafe {
+            libsolv_sys::selection_subtract(pool.pool, &mut self.queue.queue, &mut other.queue);
+        }
+    }
+
+    /// Keep only the solvables for the architecture
+    pub fn filter_arch(&mut self, pool: &mut Pool, arch: &str) {
+        self.retain(pool, |s| s.arch() == arch);
+    }
+
+    /// Keep only the solvables from the repo with this id
+    pub fn filter_repo(&mut self, pool: &mut Pool, repoid: libsolv_sys::Id) {
+        self.retain(pool, |s| s.repo().id() == repoid);
+    }
+
+    /// Keep only the solvables where `f` returns true.  The selection
+    /// is replaced by a single `SOLVER_SOLVABLE_ONE_OF` entry, with
+    /// the set of solvables added to the pool
+    pub fn retain(&mut self, pool: &mut Pool, mut f: impl FnMut(&Solvable) -> bool) {
+        let mut pkgs = Queue::new();
+        for solvable in self.solvables(pool).iter().filter(|s| f(s)) {
+            pkgs.insert(pkgs.len(), solvable.id());
+        }
+        self.queue = Queue::new();
+        if !pkgs.is_empty() {
+            let what = unsafe { libsolv_sys::pool_queuetowhatprovides(pool.pool, &mut pkgs.queue) };
+            self.queue
+                .push2(libsolv_sys::SOLVER_SOLVABLE_ONE_OF as libsolv_sys::Id, what);
+        }
+    }
+
+    /// Expand the selection to the list of solvables
+    pub fn solvables<'pool>(&self, pool: &'pool Pool) -> Vec<Solvable<'pool>> {
+        let mut selection = self.queue.clone();
+        let mut pkgs = Queue::new();
+        unsafe {
+            libsolv_sys::selection_solvables(pool.pool, &mut selection.queue, &mut pkgs.queue);
+        }
+        pkgs.to_vec()
+            .into_iter()
+            .filter_map(|p| pool.solvable(p))
+            .collect()
+    }
+
+    /// Jobs applying the action of `builder` to the selection
+    pub fn jobs(&self, builder: JobBuilder) -> Vec<Job> {
+        self.queue
+            .to_vec()
+            .chunks_exact(2)
+            .map(|pair| builder.select(pair[0] as u32, pair[1]))
+            .collect()
+    }
+}
@@ -1,59 +1,225 @@
 use std::ffi;
+use std::mem;
+use std::ptr;
 
-#[derive(Debug)]
-pub struct Solvable {
+use crate::pool::Pool;
+use crate::queue::Queue;
+use crate::repo::Repo;
+
+/// Reference of an update (patch) to an issue tracker, like a CVE or a
+/// bug
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateReference {
+    /// `cve`, `bugzilla`, ...
+    pub kind: String,
+    pub id: String,
+    pub title: String,
+}
+
+/// Solvable stored in the pool.  It cannot outlive the pool that owns
+/// it
+#[derive(Debug, Clone, Copy)]
+pub struct Solvable<'pool> {
     pub(crate) solvable: *mut libsolv_sys::Solvable,
+    pub(crate) pool: &'pool Pool,
 }
 
-impl Solvable {
-    pub fn lookup_type(&mut self, keyname: libsolv_sys::Id) -> libsolv_sys::Id {
+impl<'pool> Solvable<'pool> {
+    /// Solvable with id `p`, if the slot is in use
+    pub(crate) fn from_id(pool: &'pool Pool, p: libsolv_sys::Id) -> Option<Self> {
+        unsafe {
+            if p <= 0 || p >= (*pool.pool).nsolvables {
+                return None;
+            }
+            let solvable = (*pool.pool).solvables.offset(p as isize);
+            if (*solvable).repo.is_null() {
+                None
+            } else {
+                Some(Solvable { solvable, pool })
+            }
+        }
+    }
+
+    pub fn id(&self) -> libsolv_sys::Id {
+        unsafe { self.solvable.offset_from((*self.pool.pool).solvables) as libsolv_sys::Id }
+    }
+
+    pub fn pool(&self) -> &'pool Pool {
+        self.pool
+    }
+
+    pub fn repo(&self) -> Repo<'pool> {
+        Repo {
+            repo: unsafe { (*self.solvable).repo },
+            pool: self.pool,
+        }
+    }
+
+    pub fn lookup_type(&self, keyname: libsolv_sys::Id) -> libsolv_sys::Id {
         unsafe { libsolv_sys::solvable_lookup_type(self.solvable, keyname) }
     }
 
-    pub fn lookup_id(&mut self, keyname: libsolv_sys::Id) -> libsolv_sys::Id {
+    pub fn lookup_id(&self, keyname: libsolv_sys::Id) -> libsolv_sys::Id {
         unsafe { libsolv_sys::solvable_lookup_id(self.solvable, keyname) }
     }
 
-    pub fn lookup_num(&mut self, keyname: libsolv_sys::Id, default: u64) -> u64 {
+    pub fn lookup_num(&self, keyname: libsolv_sys::Id, default: u64) -> u64 {
         unsafe { libsolv_sys::solvable_lookup_num(self.solvable, keyname, default) }
     }
 
-    pub fn lookup_sizek(&mut self, keyname: libsolv_sys::Id, default: u64) -> u64 {
+    pub fn lookup_sizek(&self, keyname: libsolv_sys::Id, default: u64) -> u64 {
         unsafe { libsolv_sys::solvable_lookup_sizek(self.solvable, keyname, default) }
     }
 
-    pub fn lookup_str(&mut self, keyname: libsolv_sys::Id) -> String {
-        unsafe {
-            ffi::CStr::from_ptr(libsolv_sys::solvable_lookup_str(self.solvable, keyname))
-                .to_string_lossy()
-                .into_owned()
-        }
+    pub fn lookup_str(&self, keyname: libsolv_sys::Id) -> String {
+        unsafe { to_string(libsolv_sys::solvable_lookup_str(self.solvable, keyname)) }
     }
 
-    pub fn lookup_str_poollang(&mut self, keyname: libsolv_sys::Id) -> String {
+    pub fn lookup_str_poollang(&self, keyname: libsolv_sys::Id) -> String {
         unsafe {
-            ffi::CStr::from_ptr(libsolv_sys::solvable_lookup_str_poollang(
+            to_string(libsolv_sys::solvable_lookup_str_poollang(
                 self.solvable,
                 keyname,
             ))
-            .to_string_lossy()
-            .into_owned()
         }
     }
 
-    pub fn name(&mut self) -> String {
+    /// Dependencies of the array `keyname`, like
+    /// `SOLVABLE_CONFLICTS`
+    pub fn lookup_deparray(&self, keyname: libsolv_sys::Id) -> Vec<libsolv_sys::Id> {
+        let mut queue = Queue::new();
+        unsafe {
+            libsolv_sys::solvable_lookup_deparray(self.solvable, keyname, &mut queue.queue, -1);
+        }
+        queue.to_vec()
+    }
+
+    pub fn name(&self) -> String {
         self.lookup_str(libsolv_sys::solv_knownid_SOLVABLE_NAME as i32)
     }
-    pub fn evr(&mut self) -> String {
+    pub fn evr(&self) -> String {
         self.lookup_str(libsolv_sys::solv_knownid_SOLVABLE_EVR as i32)
     }
-    pub fn arch(&mut self) -> String {
+    pub fn arch(&self) -> String {
         self.lookup_str(libsolv_sys::solv_knownid_SOLVABLE_ARCH as i32)
     }
-    pub fn nevra(&mut self) -> String {
+    pub fn vendor(&self) -> String {
+        self.lookup_str(libsolv_sys::solv_knownid_SOLVABLE_VENDOR as i32)
+    }
+    pub fn nevra(&self) -> String {
         format!("{}-{}.{}", self.name(), self.evr(), self.arch())
     }
-    pub fn buildtime(&mut self) -> u64 {
+    pub fn buildtime(&self) -> u64 {
         self.lookup_num(libsolv_sys::solv_knownid_SOLVABLE_BUILDTIME as i32, 0)
     }
+    /// Conflicts of the solvable, as dependency ids
+    pub fn conflicts(&self) -> Vec<libsolv_sys::Id> {
+        self.lookup_deparray(libsolv_sys::solv_knownid_SOLVABLE_CONFLICTS as i32)
+    }
+
+    /// Category of a patch: `security`, `recommended`, `optional`, ...
+    pub fn patch_category(&self) -> String {
+        self.lookup_str(libsolv_sys::solv_knownid_SOLVABLE_PATCHCATEGORY as i32)
+    }
+
+    /// Severity of a patch: `critical`, `important`, `moderate`, ...
+    pub fn update_severity(&self) -> String {
+        self.lookup_str(libsolv_sys::solv_knownid_UPDATE_SEVERITY as i32)
+    }
+
+    /// References of a patch, stored as sub-structures of
+    /// `UPDATE_REFERENCE`
+    pub fn update_references(&self) -> Vec<UpdateReference> {
+        let pool = self.pool.pool;
+        let lookup = |keyname: libsolv_sys::solv_knownid| unsafe {
+            to_string(libsolv_sys::pool_lookup_str(
+                pool,
+                libsolv_sys::SOLVID_POS,
+                keyname as i32,
+            ))
+        };
+
+        let mut references = Vec::new();
+        unsafe {
+            let mut di = mem::MaybeUninit::<libsolv_sys::Dataiterator>::uninit();
+            libsolv_sys::dataiterator_init(
+                di.as_mut_ptr(),
+                pool,
+                ptr::null_mut(),
+                self.id(),
+                libsolv_sys::solv_knownid_UPDATE_REFERENCE as i32,
+                ptr::null(),
+                0,
+            );
+            let mut di = di.assume_init();
+            while libsolv_sys::dataiterator_step(&mut di) != 0 {
+                libsolv_sys::dataiterator_setpos(&mut di);
+                references.push(UpdateReference {
+                    kind: lookup(libsolv_sys::solv_knownid_UPDATE_REFERENCE_TYPE),
+                    id: lookup(libsolv_sys::solv_knownid_UPDATE_REFERENCE_ID),
+                    title: lookup(libsolv_sys::solv_knownid_UPDATE_REFERENCE_TITLE),
+                });
+            }
+            libsolv_sys::dataiterator_free(&mut di);
+        }
+        references
+    }
+}
+
+// Missing strings are returned as NULL
+unsafe fn to_string(string: *const std::os::raw::c_char) -> String {
+    if string.is_null() {
+        String::new()
+    } else {
+        ffi::CStr::from_ptr(string).to_string_lossy().into_owned()
+    }
+}
+
+/// Iterator over the solvables of the pool, or of a single repo.
+/// Freed slots are skipped
+#[derive(Debug)]
+pub struct Solvables<'pool> {
+    pub(crate) pool: &'pool Pool,
+    pub(crate) repo: *mut libsolv_sys::Repo,
+    pub(crate) next: libsolv_sys::Id,
+    pub(crate) end: libsolv_sys::Id,
+}
+
+impl<'pool> Solvables<'pool> {
+    pub(crate) fn new(
+        pool: &'pool Pool,
+        repo: *mut libsolv_sys::Repo,
+        start: libsolv_sys::Id,
+        end: libsolv_sys::Id,
+    ) -> Self {
+        Solvables {
+            pool,
+            repo,
+            next: start,
+            end,
+        }
+    }
+
+    /// All the solvables in the pool
+    pub(crate) fn all(pool: &'pool Pool) -> Self {
+        // The first two solvables are reserved by libsolv
+        Solvables::new(pool, ptr::null_mut(), 2, unsafe { (*pool.pool).nsolvables })
+    }
+}
+
+impl<'pool> Iterator for Solvables<'pool> {
+    type Item = Solvable<'pool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.end {
+            let p = self.next;
+            self.next += 1;
+            if let Some(solvable) = Solvable::from_id(self.pool, p) {
+                if self.repo.is_null() || unsafe { (*solvable.solvable).repo } == self.repo {
+                    return Some(solvable);
+                }
+            }
+        }
+        None
+    }
 }
@@ -1,34 +1,327 @@
+use std::ffi;
+use std::os::raw::c_char;
+
+use crate::job::{self, Job};
 use crate::pool::Pool;
 use crate::queue::Queue;
+use crate::solvable::Solvable;
 use crate::transaction::Transaction;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleInfo {
+    Unknown = libsolv_sys::SolverRuleinfo_SOLVER_RULE_UNKNOWN as isize,
+    Pkg = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG as isize,
+    PkgNotInstallable = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_NOT_INSTALLABLE as isize,
+    PkgNothingProvidesDep =
+        libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_NOTHING_PROVIDES_DEP as isize,
+    PkgRequires = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_REQUIRES as isize,
+    PkgSelfConflict = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_SELF_CONFLICT as isize,
+    PkgConflicts = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_CONFLICTS as isize,
+    PkgSameName = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_SAME_NAME as isize,
+    PkgObsoletes = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_OBSOLETES as isize,
+    PkgImplicitObsoletes = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_IMPLICIT_OBSOLETES as isize,
+    PkgInstalledObsoletes =
+        libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_INSTALLED_OBSOLETES as isize,
+    PkgRecommends = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_RECOMMENDS as isize,
+    PkgConstrains = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_CONSTRAINS as isize,
+    PkgSupplements = libsolv_sys::SolverRuleinfo_SOLVER_RULE_PKG_SUPPLEMENTS as isize,
+    Update = libsolv_sys::SolverRuleinfo_SOLVER_RULE_UPDATE as isize,
+    Feature = libsolv_sys::SolverRuleinfo_SOLVER_RULE_FEATURE as isize,
+    Job = libsolv_sys::SolverRuleinfo_SOLVER_RULE_JOB as isize,
+    JobNothingProvidesDep =
+        libsolv_sys::SolverRuleinfo_SOLVER_RULE_JOB_NOTHING_PROVIDES_DEP as isize,
+    JobProvidedBySystem = libsolv_sys::SolverRuleinfo_SOLVER_RULE_JOB_PROVIDED_BY_SYSTEM as isize,
+    JobUnknownPackage = libsolv_sys::SolverRuleinfo_SOLVER_RULE_JOB_UNKNOWN_PACKAGE as isize,
+    JobUnsupported = libsolv_sys::SolverRuleinfo_SOLVER_RULE_JOB_UNSUPPORTED as isize,
+    Distupgrade = libsolv_sys::SolverRuleinfo_SOLVER_RULE_DISTUPGRADE as isize,
+    Infarch = libsolv_sys::SolverRuleinfo_SOLVER_RULE_INFARCH as isize,
+    Choice = libsolv_sys::SolverRuleinfo_SOLVER_RULE_CHOICE as isize,
+    Learnt = libsolv_sys::SolverRuleinfo_SOLVER_RULE_LEARNT as isize,
+    Best = libsolv_sys::SolverRuleinfo_SOLVER_RULE_BEST as isize,
+    Yumobs = libsolv_sys::SolverRuleinfo_SOLVER_RULE_YUMOBS as isize,
+    Recommends = libsolv_sys::SolverRuleinfo_SOLVER_RULE_RECOMMENDS as isize,
+    Blacklist = libsolv_sys::SolverRuleinfo_SOLVER_RULE_BLACKLIST as isize,
+    StrictRepoPriority = libsolv_sys::SolverRuleinfo_SOLVER_RULE_STRICT_REPO_PRIORITY as isize,
+}
+
+impl TryFrom<u32> for RuleInfo {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            x if x == RuleInfo::Unknown as u32 => Ok(RuleInfo::Unknown),
+            x if x == RuleInfo::Pkg as u32 => Ok(RuleInfo::Pkg),
+            x if x == RuleInfo::PkgNotInstallable as u32 => Ok(RuleInfo::PkgNotInstallable),
+            x if x == RuleInfo::PkgNothingProvidesDep as u32 => Ok(RuleInfo::PkgNothingProvidesDep),
+            x if x == RuleInfo::PkgRequires as u32 => Ok(RuleInfo::PkgRequires),
+            x if x == RuleInfo::PkgSelfConflict as u32 => Ok(RuleInfo::PkgSelfConflict),
+            x if x == RuleInfo::PkgConflicts as u32 => Ok(RuleInfo::PkgConflicts),
+            x if x == RuleInfo::PkgSameName as u32 => Ok(RuleInfo::PkgSameName),
+            x if x == RuleInfo::PkgObsoletes as u32 => Ok(RuleInfo::PkgObsoletes),
+            x if x == RuleInfo::PkgImplicitObsoletes as u32 => Ok(RuleInfo::PkgImplicitObsoletes),
+            x if x == RuleInfo::PkgInstalledObsoletes as u32 => Ok(RuleInfo::PkgInstalledObsoletes),
+            x if x == RuleInfo::PkgRecommends as u32 => Ok(RuleInfo::PkgRecommends),
+            x if x == RuleInfo::PkgConstrains as u32 => Ok(RuleInfo::PkgConstrains),
+            x if x == RuleInfo::PkgSupplements as u32 => Ok(RuleInfo::PkgSupplements),
+            x if x == RuleInfo::Update as u32 => Ok(RuleInfo::Update),
+            x if x == RuleInfo::Feature as u32 => Ok(RuleInfo::Feature),
+            x if x == RuleInfo::Job as u32 => Ok(RuleInfo::Job),
+            x if x == RuleInfo::JobNothingProvidesDep as u32 => Ok(RuleInfo::JobNothingProvidesDep),
+            x if x == RuleInfo::JobProvidedBySystem as u32 => Ok(RuleInfo::JobProvidedBySystem),
+            x if x == RuleInfo::JobUnknownPackage as u32 => Ok(RuleInfo::JobUnknownPackage),
+            x if x == RuleInfo::JobUnsupported as u32 => Ok(RuleInfo::JobUnsupported),
+            x if x == RuleInfo::Distupgrade as u32 => Ok(RuleInfo::Distupgrade),
+            x if x == RuleInfo::Infarch as u32 => Ok(RuleInfo::Infarch),
+            x if x == RuleInfo::Choice as u32 => Ok(RuleInfo::Choice),
+            x if x == RuleInfo::Learnt as u32 => Ok(RuleInfo::Learnt),
+            x if x == RuleInfo::Best as u32 => Ok(RuleInfo::Best),
+            x if x == RuleInfo::Yumobs as u32 => Ok(RuleInfo::Yumobs),
+            x if x == RuleInfo::Recommends as u32 => Ok(RuleInfo::Recommends),
+            x if x == RuleInfo::Blacklist as u32 => Ok(RuleInfo::Blacklist),
+            x if x == RuleInfo::StrictRepoPriority as u32 => Ok(RuleInfo::StrictRepoPriority),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Rule responsible for a problem, as reported by `solver_ruleinfo`
+#[derive(Debug, Clone)]
+pub struct ProblemRule {
+    pub info: RuleInfo,
+    pub source: libsolv_sys::Id,
+    pub target: libsolv_sys::Id,
+    pub dep: libsolv_sys::Id,
+    pub description: String,
+}
+
+/// Part of a solution, like removing a job or allowing a downgrade
+#[derive(Debug, Clone)]
+pub struct SolutionElement {
+    pub p: libsolv_sys::Id,
+    pub rp: libsolv_sys::Id,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub id: libsolv_sys::Id,
+    pub elements: Vec<SolutionElement>,
+}
+
+/// Problem found by the solver.  It owns all the strings, so it can
+/// outlive the solver
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub id: libsolv_sys::Id,
+    pub description: String,
+    pub rule: ProblemRule,
+    pub solutions: Vec<Solution>,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+// Strings returned by the solver are temporary, so we copy them
+unsafe fn tmpstr(string: *const c_char) -> String {
+    if string.is_null() {
+        String::new()
+    } else {
+        ffi::CStr::from_ptr(string).to_string_lossy().into_owned()
+    }
+}
+
+pub enum SolverFlag {
+    AllowDowngrade = libsolv_sys::SOLVER_FLAG_ALLOW_DOWNGRADE as isize,
+    AllowArchChange = libsolv_sys::SOLVER_FLAG_ALLOW_ARCHCHANGE as isize,
+    AllowVendorChange = libsolv_sys::SOLVER_FLAG_ALLOW_VENDORCHANGE as isize,
+    AllowUninstall = libsolv_sys::SOLVER_FLAG_ALLOW_UNINSTALL as isize,
+    NoUpdateProvide = libsolv_sys::SOLVER_FLAG_NO_UPDATEPROVIDE as isize,
+    SplitProvides = libsolv_sys::SOLVER_FLAG_SPLITPROVIDES as isize,
+    IgnoreRecommended = libsolv_sys::SOLVER_FLAG_IGNORE_RECOMMENDED as isize,
+    AddAlreadyRecommended = libsolv_sys::SOLVER_FLAG_ADD_ALREADY_RECOMMENDED as isize,
+    NoInfArchCheck = libsolv_sys::SOLVER_FLAG_NO_INFARCHCHECK as isize,
+    AllowNameChange = libsolv_sys::SOLVER_FLAG_ALLOW_NAMECHANGE as isize,
+    KeepExplicitObsoletes = libsolv_sys::SOLVER_FLAG_KEEP_EXPLICIT_OBSOLETES as isize,
+    BestObeyPolicy = libsolv_sys::SOLVER_FLAG_BEST_OBEY_POLICY as isize,
+    NoAutoTarget = libsolv_sys::SOLVER_FLAG_NO_AUTOTARGET as isize,
+    DupAllowDowngrade = libsolv_sys::SOLVER_FLAG_DUP_ALLOW_DOWNGRADE as isize,
+    DupAllowArchChange = libsolv_sys::SOLVER_FLAG_DUP_ALLOW_ARCHCHANGE as isize,
+    DupAllowVendorChange = libsolv_sys::SOLVER_FLAG_DUP_ALLOW_VENDORCHANGE as isize,
+    DupAllowNameChange = libsolv_sys::SOLVER_FLAG_DUP_ALLOW_NAMECHANGE as isize,
+    KeepOrphans = libsolv_sys::SOLVER_FLAG_KEEP_ORPHANS as isize,
+    BreakOrphans = libsolv_sys::SOLVER_FLAG_BREAK_ORPHANS as isize,
+    FocusInstalled = libsolv_sys::SOLVER_FLAG_FOCUS_INSTALLED as isize,
+    YumObsoletes = libsolv_sys::SOLVER_FLAG_YUM_OBSOLETES as isize,
+    NeedUpdateProvide = libsolv_sys::SOLVER_FLAG_NEED_UPDATEPROVIDE as isize,
+    UrpmReorder = libsolv_sys::SOLVER_FLAG_URPM_REORDER as isize,
+    FocusBest = libsolv_sys::SOLVER_FLAG_FOCUS_BEST as isize,
+    StrongRecommends = libsolv_sys::SOLVER_FLAG_STRONG_RECOMMENDS as isize,
+    InstallAlsoUpdates = libsolv_sys::SOLVER_FLAG_INSTALL_ALSO_UPDATES as isize,
+    OnlyNamespaceRecommended = libsolv_sys::SOLVER_FLAG_ONLY_NAMESPACE_RECOMMENDED as isize,
+    StrictRepoPriority = libsolv_sys::SOLVER_FLAG_STRICT_REPO_PRIORITY as isize,
+}
+
+/// Solver for the pool.  It cannot outlive the pool
 #[derive(Debug)]
-pub struct Solver {
+pub struct Solver<'pool> {
     pub(crate) solver: *mut libsolv_sys::Solver,
+    pub(crate) pool: &'pool Pool,
 }
 
-impl Solver {
-    pub fn new(pool: Pool) -> Self {
+impl<'pool> Solver<'pool> {
+    pub fn new(pool: &'pool Pool) -> Self {
         unsafe {
             let solver = libsolv_sys::solver_create(pool.pool);
-            Solver { solver }
+            Solver { solver, pool }
         }
     }
 
+    pub fn set_flag(&mut self, flag: SolverFlag, status: bool) -> bool {
+        unsafe { libsolv_sys::solver_set_flag(self.solver, flag as i32, status as i32) != 0 }
+    }
+
+    pub fn get_flag(&self, flag: SolverFlag) -> bool {
+        unsafe { libsolv_sys::solver_get_flag(self.solver, flag as i32) != 0 }
+    }
+
     pub fn solve(&mut self, mut job: Queue) -> i32 {
         unsafe { libsolv_sys::solver_solve(self.solver, &mut job.queue) }
     }
 
-    pub fn transaction(&mut self) -> Transaction {
+    /// Solve the jobs, returning the number of problems found
+    pub fn solve_jobs(&mut self, jobs: &[Job]) -> i32 {
+        self.solve(job::queue(jobs))
+    }
+
+    /// Installed solvables that are not in any repository.  They are
+    /// found when solving a distupgrade job
+    pub fn orphaned(&self) -> Vec<Solvable<'pool>> {
+        let mut queue = Queue::new();
+        unsafe {
+            libsolv_sys::solver_get_orphaned(self.solver, &mut queue.queue);
+        }
+        queue
+            .to_vec()
+            .into_iter()
+            .filter_map(|p| Solvable::from_id(self.pool, p))
+            .collect()
+    }
+
+    pub fn transaction(&mut self) -> Transaction<'pool> {
         unsafe {
             let transaction = libsolv_sys::solver_create_transaction(self.solver);
-            Transaction { transaction }
+            Transaction {
+                transaction,
+                pool: self.pool,
+            }
+        }
+    }
+}
+
+// From problems
+impl<'pool> Solver<'pool> {
+    pub fn problem_count(&mut self) -> u32 {
+        unsafe { libsolv_sys::solver_problem_count(self.solver) }
+    }
+
+    pub fn find_problem_rule(&mut self, problem: libsolv_sys::Id) -> libsolv_sys::Id {
+        unsafe { libsolv_sys::solver_findproblemrule(self.solver, problem) }
+    }
+
+    pub fn rule_info(&mut self, rule: libsolv_sys::Id) -> ProblemRule {
+        let mut source = 0;
+        let mut target = 0;
+        let mut dep = 0;
+        unsafe {
+            let info =
+                libsolv_sys::solver_ruleinfo(self.solver, rule, &mut source, &mut target, &mut dep);
+            ProblemRule {
+                info: info.try_into().unwrap_or(RuleInfo::Unknown),
+                source,
+                target,
+                dep,
+                description: tmpstr(libsolv_sys::solver_problemruleinfo2str(
+                    self.solver,
+                    info,
+                    source,
+                    target,
+                    dep,
+                )),
+            }
+        }
+    }
+
+    pub fn problem2str(&mut self, problem: libsolv_sys::Id) -> String {
+        unsafe { tmpstr(libsolv_sys::solver_problem2str(self.solver, problem)) }
+    }
+
+    pub fn solution_count(&mut self, problem: libsolv_sys::Id) -> u32 {
+        unsafe { libsolv_sys::solver_solution_count(self.solver, problem) }
+    }
+
+    pub fn solution_elements(
+        &mut self,
+        problem: libsolv_sys::Id,
+        solution: libsolv_sys::Id,
+    ) -> Vec<SolutionElement> {
+        let mut elements = Vec::new();
+        let mut element = 0;
+        loop {
+            let mut p = 0;
+            let mut rp = 0;
+            unsafe {
+                element = libsolv_sys::solver_next_solutionelement(
+                    self.solver,
+                    problem,
+                    solution,
+                    element,
+                    &mut p,
+                    &mut rp,
+                );
+                if element == 0 {
+                    break;
+                }
+                elements.push(SolutionElement {
+                    p,
+                    rp,
+                    description: tmpstr(libsolv_sys::solver_solutionelement2str(
+                        self.solver,
+                        p,
+                        rp,
+                    )),
+                });
+            }
+        }
+        elements
+    }
+
+    /// All the problems found in the last `solve`
+    pub fn problems(&mut self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for problem in 1..=self.problem_count() as libsolv_sys::Id {
+            let rule = self.find_problem_rule(problem);
+            let solutions = (1..=self.solution_count(problem) as libsolv_sys::Id)
+                .map(|solution| Solution {
+                    id: solution,
+                    elements: self.solution_elements(problem, solution),
+                })
+                .collect();
+            problems.push(Problem {
+                id: problem,
+                description: self.problem2str(problem),
+                rule: self.rule_info(rule),
+                solutions,
+            });
         }
+        problems
     }
 }
 
 // From solverdebug
-impl Solver {
+impl<'pool> Solver<'pool> {
     pub fn print_problem_info(&mut self, problem: libsolv_sys::Id) {
         unsafe {
             libsolv_sys::solver_printprobleminfo(self.solver, problem);
@@ -36,7 +329,7 @@ impl Solver {
     }
 }
 
-impl Drop for Solver {
+impl Drop for Solver<'_> {
     fn drop(&mut self) {
         unsafe {
             libsolv_sys::solver_free(self.solver);
@@ -1,8 +1,10 @@
 use bitflags::bitflags;
 
-use crate::pool::Pool;
+use crate::pool::{Package, Pool};
 use crate::queue::Queue;
+use crate::solvable::Solvable;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransactionType {
     Ignore = libsolv_sys::SOLVER_TRANSACTION_IGNORE as isize,
     Erase = libsolv_sys::SOLVER_TRANSACTION_ERASE as isize,
@@ -20,6 +22,8 @@ pub enum TransactionType {
     MultiInstall = libsolv_sys::SOLVER_TRANSACTION_MULTIINSTALL as isize,
     MultiReinstall = libsolv_sys::SOLVER_TRANSACTION_MULTIREINSTALL as isize,
     MaxType = libsolv_sys::SOLVER_TRANSACTION_MAXTYPE as isize,
+    ArchChange = libsolv_sys::SOLVER_TRANSACTION_ARCHCHANGE as isize,
+    VendorChange = libsolv_sys::SOLVER_TRANSACTION_VENDORCHANGE as isize,
 }
 
 impl TryFrom<i32> for TransactionType {
@@ -43,12 +47,15 @@ impl TryFrom<i32> for TransactionType {
             x if x == TransactionType::MultiInstall as i32 => Ok(TransactionType::MultiInstall),
             x if x == TransactionType::MultiReinstall as i32 => Ok(TransactionType::MultiReinstall),
             x if x == TransactionType::MaxType as i32 => Ok(TransactionType::MaxType),
+            x if x == TransactionType::ArchChange as i32 => Ok(TransactionType::ArchChange),
+            x if x == TransactionType::VendorChange as i32 => Ok(TransactionType::VendorChange),
             _ => Err(()),
         }
     }
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TransactionMode: u32 {
     const SHOW_ACTIVE = libsolv_sys::SOLVER_TRANSACTION_SHOW_ACTIVE;
     const SHOW_ALL = libsolv_sys::SOLVER_TRANSACTION_SHOW_ALL;
@@ -63,16 +70,29 @@ bitflags! {
     }
 }
 
+/// Class of steps of a transaction, as returned by `classify`.  For
+/// arch and vendor changes, `from` and `to` are the old and new arch
+/// or vendor ids
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionClass {
+    pub kind: TransactionType,
+    pub count: i32,
+    pub from: libsolv_sys::Id,
+    pub to: libsolv_sys::Id,
+}
+
+/// Transaction for the pool.  It cannot outlive the pool
 #[derive(Debug)]
-pub struct Transaction {
+pub struct Transaction<'pool> {
     pub(crate) transaction: *mut libsolv_sys::Transaction,
+    pub(crate) pool: &'pool Pool,
 }
 
-impl Transaction {
-    pub fn new(pool: &Pool) -> Self {
+impl<'pool> Transaction<'pool> {
+    pub fn new(pool: &'pool Pool) -> Self {
         unsafe {
             let transaction = libsolv_sys::transaction_create(pool.pool);
-            Transaction { transaction }
+            Transaction { transaction, pool }
         }
     }
 
@@ -84,7 +104,7 @@ impl Transaction {
         unsafe {
             libsolv_sys::transaction_classify(
                 self.transaction,
-                mode.bits as i32,
+                mode.bits() as i32,
                 &mut classes.queue,
             );
         }
@@ -101,7 +121,7 @@ impl Transaction {
         unsafe {
             libsolv_sys::transaction_classify_pkgs(
                 self.transaction,
-                mode.bits as i32,
+                mode.bits() as i32,
                 transaction_type as i32,
                 from,
                 to,
@@ -111,7 +131,65 @@ impl Transaction {
     }
 }
 
-impl Drop for Transaction {
+impl<'pool> Transaction<'pool> {
+    /// Classes of steps of the transaction
+    pub fn classes(&mut self, mode: TransactionMode) -> Vec<TransactionClass> {
+        let mut classes = Queue::new();
+        self.classify(mode, &mut classes);
+        classes
+            .to_vec()
+            .chunks_exact(4)
+            .filter_map(|class| {
+                Some(TransactionClass {
+                    kind: class[0].try_into().ok()?,
+                    count: class[1],
+                    from: class[2],
+                    to: class[3],
+                })
+            })
+            .collect()
+    }
+
+    /// Solvables in the class.  For erases, upgrades and downgrades
+    /// these are the installed solvables
+    pub fn class_solvables(
+        &mut self,
+        mode: TransactionMode,
+        class: &TransactionClass,
+    ) -> Vec<Solvable<'pool>> {
+        let mut pkgs = Queue::new();
+        self.classify_pkgs(mode, class.kind, class.from, class.to, &mut pkgs);
+        pkgs.to_vec()
+            .into_iter()
+            .filter_map(|p| self.pool.solvable(p))
+            .collect()
+    }
+
+    /// Solvable that replaces the installed one
+    pub fn replacement(&mut self, solvable: &Solvable) -> Option<Solvable<'pool>> {
+        let p = self.obs_pkg(solvable.id());
+        self.pool.solvable(p)
+    }
+
+    /// Packages installed by the transaction
+    pub fn installs(&mut self) -> Vec<Package> {
+        let mode = TransactionMode::SHOW_OBSOLETES | TransactionMode::OBSOLETE_IS_UPGRADE;
+        let mut packages = Vec::new();
+        for class in self.classes(mode) {
+            if matches!(
+                class.kind,
+                TransactionType::Install | TransactionType::MultiInstall
+            ) {
+                for s in self.class_solvables(mode, &class) {
+                    packages.push(Package::from_solvable(&s));
+                }
+            }
+        }
+        packages
+    }
+}
+
+impl Drop for Transaction<'_> {
     fn drop(&mut self) {
         unsafe {
             libsolv_sys::transaction_free(self.transaction);
@@ -0,0 +1,190 @@
+//! Ownership of the libsolv objects.  Every test creates and frees
+//! the objects in a different order, so running them under valgrind
+//! (`valgrind --leak-check=full --errors-for-leak-kinds=definite`)
+//! reports any leak or use after free
+
+use libsolv_rs::job::Job;
+use libsolv_rs::map::Map;
+use libsolv_rs::pool::Pool;
+use libsolv_rs::queue::Queue;
+use libsolv_rs::repo::{RepoFlags, RepoMut};
+use libsolv_rs::selection::Selection;
+use libsolv_rs::solver::Solver;
+use libsolv_rs::transaction::Transaction;
+
+#[test]
+fn pool_create_and_drop() {
+    for _ in 0..100 {
+        let pool = Pool::new();
+        drop(pool);
+    }
+}
+
+#[test]
+fn pool_drop_frees_repos() {
+    let mut pool = Pool::new();
+    for i in 0..10 {
+        RepoMut::new(&mut pool, &format!("repo{}", i)).add_solvable_block(10);
+    }
+    assert_eq!(pool.repos().count(), 10);
+    assert_eq!(pool.solvables().count(), 100);
+
+    // The repos are not freed one by one, the pool frees them all
+    drop(pool);
+}
+
+#[test]
+fn repo_add_missing_solv() {
+    let mut pool = Pool::new();
+    let mut repo = RepoMut::new(&mut pool, "missing");
+    assert_eq!(repo.add_solv("/nonexistent/solv", RepoFlags::empty()), -1);
+    assert_eq!(repo.repo().nsolvables(), 0);
+}
+
+#[test]
+fn repo_free() {
+    let mut pool = Pool::new();
+    let first = RepoMut::new(&mut pool, "first").id();
+    let second = RepoMut::new(&mut pool, "second").id();
+
+    pool.repo_mut(first).unwrap().free(true);
+    assert!(pool.repo_mut(first).is_none());
+    let names: Vec<_> = pool.repos().map(|r| r.name()).collect();
+    assert_eq!(names, vec!["second"]);
+
+    pool.repo_mut(second).unwrap().free(true);
+    assert_eq!(pool.repos().count(), 0);
+}
+
+#[test]
+fn repo_free_with_solvables() {
+    let mut pool = Pool::new();
+    for _ in 0..100 {
+        let mut repo = RepoMut::new(&mut pool, "test");
+        repo.add_solvable_block(100);
+        repo.free(true);
+    }
+    assert_eq!(pool.repos().count(), 0);
+    assert_eq!(pool.solvables().count(), 0);
+}
+
+#[test]
+fn repo_empty() {
+    let mut pool = Pool::new();
+    let mut repo = RepoMut::new(&mut pool, "test");
+    repo.add_solvable_block(10);
+    assert_eq!(repo.repo().nsolvables(), 10);
+    repo.empty(true);
+    assert_eq!(repo.repo().nsolvables(), 0);
+    assert_eq!(pool.solvables().count(), 0);
+}
+
+#[test]
+fn free_solvables() {
+    let mut pool = Pool::new();
+    let mut repo = RepoMut::new(&mut pool, "test");
+    let start = repo.add_solvable_block(10);
+    let p = repo.add_solvable();
+    repo.free_solvable(p, true);
+    repo.free_solvable_block(start, 5, true);
+    assert_eq!(repo.repo().nsolvables(), 5);
+}
+
+#[test]
+fn solvables_after_growing_the_pool() {
+    let mut pool = Pool::new();
+    let first = RepoMut::new(&mut pool, "first").add_solvable();
+    // Reallocates the solvables of the pool
+    RepoMut::new(&mut pool, "second").add_solvable_block(1000);
+
+    let solvable = pool.solvable(first).unwrap();
+    assert_eq!(solvable.id(), first);
+    assert_eq!(solvable.repo().name(), "first");
+}
+
+#[test]
+fn installed_repo() {
+    let mut pool = Pool::new();
+    assert!(pool.installed().is_none());
+    let id = {
+        let mut repo = RepoMut::new(&mut pool, "@System");
+        repo.set_installed();
+        repo.id()
+    };
+    assert_eq!(pool.installed().map(|r| r.id()), Some(id));
+
+    pool.unset_installed();
+    assert!(pool.installed().is_none());
+    pool.repo_mut(id).unwrap().set_disabled(true);
+    assert!(pool.repos().next().is_some_and(|r| r.disabled()));
+}
+
+#[test]
+fn str2id() {
+    let mut pool = Pool::new();
+    assert_eq!(pool.str2id("not-there", false), 0);
+    let ids: Vec<_> = (0..1000)
+        .map(|i| pool.str2id(&format!("name{}", i), true))
+        .collect();
+    assert_eq!(pool.str2id("name0", false), ids[0]);
+    assert_eq!(pool.id2str(ids[999]), "name999");
+}
+
+#[test]
+fn whatprovides_needs_the_index() {
+    let mut pool = Pool::new();
+    let name = pool.str2id("name", true);
+    assert!(Selection::whatprovides(&pool, name).is_none());
+    assert!(Selection::name(&pool, "name").is_none());
+
+    pool.create_whatprovides();
+    assert!(Selection::name(&pool, "name").is_some_and(|s| s.is_empty()));
+    assert!(Selection::whatprovides(&pool, name).is_some());
+    assert!(Selection::whatprovides(&pool, 0).is_none());
+    assert!(Selection::whatprovides(&pool, 1 << 24).is_none());
+    assert!(Selection::whatprovides(&pool, (0x8000_0000u32 | 1000) as i32).is_none());
+}
+
+#[test]
+fn selection_outlives_the_borrow() {
+    let mut pool = Pool::new();
+    RepoMut::new(&mut pool, "test").add_solvable_block(10);
+    pool.create_whatprovides();
+
+    let mut selection = Selection::name(&pool, "name").unwrap();
+    // The solvables are added to the whatprovides data of the pool
+    selection.retain(&mut pool, |_| true);
+    selection.filter_arch(&mut pool, "x86_64");
+    assert!(selection.is_empty());
+    assert!(selection.solvables(&pool).is_empty());
+    assert!(Job::install().selection(&selection).is_empty());
+}
+
+#[test]
+fn solver_and_transaction_drop_before_pool() {
+    let mut pool = Pool::new();
+    RepoMut::new(&mut pool, "@System").set_installed();
+    pool.add_file_provides();
+    pool.create_whatprovides();
+
+    for _ in 0..10 {
+        let mut solver = Solver::new(&pool);
+        assert_eq!(solver.solve_jobs(&[Job::distupgrade().all()]), 0);
+        let transaction = solver.transaction();
+        drop(solver);
+        drop(transaction);
+        drop(Transaction::new(&pool));
+    }
+}
+
+#[test]
+fn queue_and_map_drop() {
+    for _ in 0..100 {
+        let mut queue = Queue::with_capacity(10);
+        queue.push2(1, 2);
+        assert_eq!(queue.to_vec(), vec![1, 2]);
+
+        let mut map = Map::with_capacity(64);
+        map.resize(1024);
+    }
+}
@@ -33,6 +33,7 @@ fn main() {
         .header(include_path.join("solver.h").to_str().unwrap())
         .header(include_path.join("solverdebug.h").to_str().unwrap())
         .header(include_path.join("selection.h").to_str().unwrap())
+        .header(include_path.join("evr.h").to_str().unwrap())
         .header(include_path.join("knownid.h").to_str().unwrap())
         .header(include_path.join("repo_appdata.h").to_str().unwrap())
         .header(include_path.join("repo_autopattern.h").to_str().unwrap())
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::path;
+
+use configparser::ini;
+use libsolv_rs::selection::Selection;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{
+    baseproduct, role_jobs, system_pool, without_installed, zypper_solver, Config, Error,
+    Installation, Result, Roles, SystemConfig, PROVIDES_PREFIX, SYSTEM_SECTION,
+};
+
+// Keys of a role that are lists, each one also with the `+` and `-`
+// variants
+static LIST_KEYS: [&str; 5] = [
+    "products",
+    "patterns",
+    "packages",
+    "patterns_opt",
+    "packages_opt",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The configuration works, but maybe not as expected
+    Warning,
+    /// The configuration cannot be used
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Problem found in the configuration
+#[derive(Debug, Serialize)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    /// File with the problem, when it is local to one file
+    pub file: Option<path::PathBuf>,
+    /// Role with the problem, if any
+    pub role: Option<String>,
+    pub message: String,
+}
+
+/// Result of validating the configuration files against the
+/// repositories of the system
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigCheck {
+    pub issues: Vec<ConfigIssue>,
+}
+
+fn is_known_key(section: &str, key: &str) -> bool {
+    if section == SYSTEM_SECTION {
+        return key == "role";
+    }
+    if key == "inherits" {
+        return section != "default";
+    }
+    let key = key.trim_end_matches(['+', '-']);
+    LIST_KEYS.contains(&key)
+}
+
+/// Dependency of a `provides:` entry, in the packages or the optional
+/// packages of a role
+fn provides<'a>(kind: &str, value: &'a str) -> Option<&'a str> {
+    match kind {
+        "package" | "optional package" => value.strip_prefix(PROVIDES_PREFIX),
+        _ => None,
+    }
+}
+
+impl ConfigCheck {
+    /// Check the configuration files.  The roles are resolved with the
+    /// repositories of the system in `root`
+    pub fn new(paths: &[path::PathBuf], root: &path::Path) -> Result<ConfigCheck> {
+        let mut check = ConfigCheck::default();
+        check.check_files(paths);
+
+        let cfg = match Config::load(paths) {
+            Ok(cfg) => cfg,
+            // The file that cannot be parsed is already reported
+            Err(Error::Config(_)) => return Ok(check),
+            Err(e) => return Err(e),
+        };
+
+        let roles = match Roles::from_config(&cfg) {
+            Ok(roles) => roles,
+            Err(e @ (Error::RoleNotFound(_) | Error::RoleCycle(_))) => {
+                check.push(Severity::Error, None, None, e.to_string());
+                return Ok(check);
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(role) = SystemConfig::from_config(&cfg).role {
+            if !roles.0.contains_key(&role) {
+                check.push(
+                    Severity::Error,
+                    None,
+                    None,
+                    format!("The system role {} is not defined", role),
+                );
+            }
+        }
+
+        check.check_roles(&roles, root)?;
+        Ok(check)
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        file: Option<&path::Path>,
+        role: Option<&str>,
+        message: String,
+    ) {
+        self.issues.push(ConfigIssue {
+            severity,
+            file: file.map(|f| f.to_path_buf()),
+            role: role.map(|r| r.to_string()),
+            message,
+        });
+    }
+
+    /// Unknown keys, and sections defined in more than one file
+    fn check_files(&mut self, paths: &[path::PathBuf]) {
+        let mut seen: HashMap<String, &path::PathBuf> = HashMap::new();
+        for path in paths {
+            let mut cfg = ini::Ini::new();
+            let map = match cfg.load(path) {
+                Ok(map) => map,
+                Err(e) => {
+                    self.push(Severity::Error, Some(path), None, e);
+                    continue;
+                }
+            };
+
+            let mut sections: Vec<_> = map.keys().collect();
+            sections.sort();
+            for section in sections {
+                match seen.get(section) {
+                    Some(other) => self.push(
+                        Severity::Warning,
+                        Some(path),
+                        Some(section),
+                        format!(
+                            "The section is also in {}, the keys set here take precedence",
+                            other.display()
+                        ),
+                    ),
+                    None => {
+                        seen.insert(section.clone(), path);
+                    }
+                }
+
+                let mut keys: Vec<_> = map[section].keys().collect();
+                keys.sort();
+                for key in keys {
+                    if !is_known_key(section, key) {
+                        self.push(
+                            Severity::Warning,
+                            Some(path),
+                            Some(section),
+                            format!("Unknown key {}", key),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Empty roles, names that are not in any repository, and roles
+    /// that the solver cannot resolve
+    fn check_roles(&mut self, roles: &Roles, root: &path::Path) -> Result<()> {
+        let mut names: Vec<_> = roles.0.keys().collect();
+        names.sort();
+
+        for role in &names {
+            let reference = &roles.0[*role];
+            if reference.patterns.is_empty() && reference.packages.is_empty() {
+                self.push(
+                    Severity::Error,
+                    None,
+                    Some(role),
+                    "The role has no patterns nor packages".to_string(),
+                );
+            }
+        }
+
+        let mut pool = match system_pool(root) {
+            Ok(pool) => pool,
+            Err(e @ Error::MissingSolvCache(_)) => {
+                self.push(
+                    Severity::Error,
+                    None,
+                    None,
+                    format!("{}, the roles are not checked", e),
+                );
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // The roles are resolved from scratch, like for the status
+        without_installed(&mut pool, |pool| {
+            // The jobs intern the names in the pool, so they are
+            // created before any selection or the solver borrows it
+            let baseproduct = baseproduct(root)?;
+            let mut jobs = Vec::new();
+            for role in &names {
+                // A role for other products is valid, but it cannot be
+                // resolved on this system
+                jobs.push(match role_jobs(pool, &baseproduct, role, roles) {
+                    Ok(jobs) => Some(jobs),
+                    Err(e @ Error::ProductMismatch(..)) => {
+                        self.push(
+                            Severity::Warning,
+                            None,
+                            Some(role),
+                            format!("{}, the role is not resolved", e),
+                        );
+                        None
+                    }
+                    Err(e) => return Err(e),
+                });
+            }
+
+            let mut solver = zypper_solver(pool, root)?;
+            for (role, jobs) in names.iter().zip(jobs) {
+                let reference = &roles.0[*role];
+                for (kind, prefix, values) in [
+                    ("product", "product:", &reference.products),
+                    ("pattern", "pattern:", &reference.patterns),
+                    ("package", "", &reference.packages),
+                    ("optional pattern", "pattern:", &reference.patterns_opt),
+                    ("optional package", "", &reference.packages_opt),
+                ] {
+                    for value in values {
+                        let selection = match provides(kind, value) {
+                            Some(dep) => Selection::provides(pool, dep),
+                            None => Selection::name(pool, &format!("{}{}", prefix, value)),
+                        };
+                        if selection.is_none_or(|s| s.is_empty()) {
+                            self.push(
+                                Severity::Error,
+                                None,
+                                Some(role),
+                                format!("The {} {} is not in any repository", kind, value),
+                            );
+                        }
+                    }
+                }
+
+                let Some(jobs) = jobs else {
+                    continue;
+                };
+                match Installation::from_jobs(&mut solver, &jobs) {
+                    Ok(_) => (),
+                    Err(e @ Error::SolverProblems(_)) => {
+                        self.push(Severity::Error, None, Some(role), e.to_string())
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn errors(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn to_shell(&self) -> String {
+        let mut lines = vec![
+            var("ERRORS", self.errors()),
+            var("WARNINGS", self.issues.len() - self.errors()),
+        ];
+        for issue in &self.issues {
+            let file = issue
+                .file
+                .as_ref()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            lines.push(
+                [
+                    var("SEVERITY", issue.severity),
+                    var("FILE", file),
+                    var("ROLE", issue.role.as_deref().unwrap_or_default()),
+                    var("MESSAGE", &issue.message),
+                ]
+                .join(" "),
+            );
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        if self.issues.is_empty() {
+            writeln!(text, "The configuration is valid")?;
+            return Ok(text);
+        }
+
+        for issue in &self.issues {
+            write!(text, "{}: ", issue.severity)?;
+            if let Some(file) = &issue.file {
+                write!(text, "{}: ", file.display())?;
+            }
+            if let Some(role) = &issue.role {
+                write!(text, "[{}] ", role)?;
+            }
+            writeln!(text, "{}", issue.message)?;
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provides_of_packages() {
+        assert_eq!(provides("package", "provides:kernel"), Some("kernel"));
+        assert_eq!(
+            provides("optional package", "provides:kernel"),
+            Some("kernel")
+        );
+        assert_eq!(provides("package", "kernel-default"), None);
+        assert_eq!(provides("pattern", "provides:kernel"), None);
+        assert_eq!(provides("optional pattern", "provides:kernel"), None);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path;
+
+use configparser::ini;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{Error, Result};
+
+/// Value of a key, and the file that set it
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigValue {
+    pub value: String,
+    pub file: path::PathBuf,
+}
+
+/// Configuration merged from several files.  Each file is parsed on
+/// its own, and a key set in a later file replaces the value from the
+/// earlier ones.  The `key+` and `key-` of a later file extend and
+/// reduce the list of `key` instead, or, if no file sets `key` because
+/// the role inherits it, they are merged with the earlier `key+` and
+/// `key-`
+#[derive(Debug, Default, Serialize)]
+pub struct Config(pub BTreeMap<String, BTreeMap<String, ConfigValue>>);
+
+/// Words of `list` followed by the ones of `values` that are not in it
+fn extend(list: &str, values: &str) -> String {
+    let mut words: Vec<_> = list.split_whitespace().collect();
+    for value in values.split_whitespace() {
+        if !words.contains(&value) {
+            words.push(value);
+        }
+    }
+    words.join(" ")
+}
+
+/// Words of `list` that are not in `values`
+fn reduce(list: &str, values: &str) -> String {
+    let values: Vec<_> = values.split_whitespace().collect();
+    list.split_whitespace()
+        .filter(|w| !values.contains(w))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Apply `value` of a later file to `op` (`+` or `-`) of `key`, a key
+/// that no file sets.  The contrary operation forgets the values
+fn merge_op(
+    keys: &mut BTreeMap<String, ConfigValue>,
+    key: &str,
+    op: char,
+    contrary: char,
+    value: ConfigValue,
+) {
+    let contrary = format!("{}{}", key, contrary);
+    if let Some(current) = keys.get_mut(&contrary) {
+        current.value = reduce(&current.value, &value.value);
+        if current.value.is_empty() {
+            keys.remove(&contrary);
+        }
+    }
+    match keys.get_mut(&format!("{}{}", key, op)) {
+        Some(current) => {
+            current.value = extend(&current.value, &value.value);
+            current.file = value.file;
+        }
+        None => {
+            keys.insert(format!("{}{}", key, op), value);
+        }
+    }
+}
+
+/// Merge the key of a later file into the keys of a section
+fn merge(keys: &mut BTreeMap<String, ConfigValue>, key: String, value: ConfigValue) {
+    let (base, op) = match key.strip_suffix('+') {
+        Some(base) => (base, Some('+')),
+        None => match key.strip_suffix('-') {
+            Some(base) => (base, Some('-')),
+            None => (key.as_str(), None),
+        },
+    };
+
+    match (op, keys.get_mut(base)) {
+        (Some('+'), Some(current)) => {
+            current.value = extend(&current.value, &value.value);
+            current.file = value.file;
+        }
+        (Some(_), Some(current)) => {
+            current.value = reduce(&current.value, &value.value);
+            current.file = value.file;
+        }
+        (Some('+'), None) => merge_op(keys, base, '+', '-', value),
+        (Some(_), None) => merge_op(keys, base, '-', '+', value),
+        (None, _) => {
+            // The new value replaces the earlier changes
+            keys.remove(&format!("{}+", base));
+            keys.remove(&format!("{}-", base));
+            keys.insert(key, value);
+        }
+    }
+}
+
+impl Config {
+    /// Load the files, from the lowest to the highest precedence
+    pub fn load(paths: &[impl AsRef<path::Path>]) -> Result<Config> {
+        let mut config = Config::default();
+
+        for path in paths {
+            let path = path.as_ref();
+            let mut cfg = ini::Ini::new();
+            let map = cfg
+                .load(path)
+                .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+
+            for (section, keys) in map {
+                let merged = config.0.entry(section).or_default();
+                // Like in a role, the keys of the file are set before
+                // they are extended, and then reduced
+                let mut keys: Vec<_> = keys.into_iter().collect();
+                keys.sort_by_key(|(key, _)| match key.chars().last() {
+                    Some('+') => 1,
+                    Some('-') => 2,
+                    _ => 0,
+                });
+                for (key, value) in keys {
+                    merge(
+                        merged,
+                        key,
+                        ConfigValue {
+                            value: value.unwrap_or_default(),
+                            file: path.to_path_buf(),
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<String> {
+        self.0
+            .get(section)
+            .and_then(|keys| keys.get(key))
+            .map(|v| v.value.clone())
+    }
+
+    pub fn sections(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn to_shell(&self) -> String {
+        let mut lines = Vec::new();
+        for (section, keys) in &self.0 {
+            for (key, value) in keys {
+                lines.push(
+                    [
+                        var("SECTION", section),
+                        var("KEY", key),
+                        var("VALUE", &value.value),
+                        var("FILE", value.file.display()),
+                    ]
+                    .join(" "),
+                );
+            }
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The merged configuration, with the file of each value as a
+    /// comment
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        for (section, keys) in &self.0 {
+            writeln!(text, "[{}]", section)?;
+            for (key, value) in keys {
+                writeln!(
+                    text,
+                    "{} = {}  # {}",
+                    key,
+                    value.value,
+                    value.file.display()
+                )?;
+            }
+            writeln!(text)?;
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Load the files, written in a temporary directory in order
+    fn load(files: &[&str]) -> Config {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (i, content) in files.iter().enumerate() {
+            let path = dir.path().join(format!("{}.cfg", i));
+            fs::write(&path, content).unwrap();
+            paths.push(path);
+        }
+        Config::load(&paths).unwrap()
+    }
+
+    fn file(cfg: &Config, section: &str, key: &str) -> String {
+        cfg.0[section][key]
+            .file
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn later_file_overrides() {
+        let cfg = load(&[
+            "[server]\npatterns = base\npackages = vim\n",
+            "[server]\npatterns = base x11\n[desktop]\npatterns = kde\n",
+        ]);
+        assert_eq!(cfg.get("server", "patterns").unwrap(), "base x11");
+        assert_eq!(file(&cfg, "server", "patterns"), "1.cfg");
+        assert_eq!(cfg.get("server", "packages").unwrap(), "vim");
+        assert_eq!(file(&cfg, "server", "packages"), "0.cfg");
+        assert_eq!(cfg.get("desktop", "patterns").unwrap(), "kde");
+        assert_eq!(cfg.sections(), vec!["desktop", "server"]);
+        assert!(cfg.get("server", "products").is_none());
+        assert!(cfg.get("none", "patterns").is_none());
+    }
+
+    #[test]
+    fn later_file_extends_and_reduces() {
+        let cfg = load(&[
+            "[server]\npackages = vim emacs\n",
+            "[server]\npackages+ = nano vim\n",
+            "[server]\npackages- = emacs\n",
+        ]);
+        assert_eq!(cfg.get("server", "packages").unwrap(), "vim nano");
+        assert_eq!(file(&cfg, "server", "packages"), "2.cfg");
+        assert!(cfg.get("server", "packages+").is_none());
+        assert!(cfg.get("server", "packages-").is_none());
+    }
+
+    #[test]
+    fn changes_of_an_inherited_key() {
+        let cfg = load(&[
+            "[server]\ninherits = base\npackages+ = vim\n",
+            "[server]\npackages+ = nano emacs\npackages- = joe\n",
+            "[server]\npackages- = emacs\n",
+        ]);
+        assert!(cfg.get("server", "packages").is_none());
+        assert_eq!(cfg.get("server", "packages+").unwrap(), "vim nano");
+        assert_eq!(cfg.get("server", "packages-").unwrap(), "joe emacs");
+
+        let cfg = load(&["[server]\npackages- = vim\n", "[server]\npackages+ = vim\n"]);
+        assert_eq!(cfg.get("server", "packages+").unwrap(), "vim");
+        assert!(cfg.get("server", "packages-").is_none());
+    }
+
+    #[test]
+    fn later_value_replaces_the_changes() {
+        let cfg = load(&[
+            "[server]\npackages+ = vim\npackages- = emacs\n",
+            "[server]\npackages = nano\n",
+        ]);
+        assert_eq!(cfg.get("server", "packages").unwrap(), "nano");
+        assert!(cfg.get("server", "packages+").is_none());
+        assert!(cfg.get("server", "packages-").is_none());
+    }
+
+    #[test]
+    fn same_file_sets_before_changing() {
+        let cfg = load(&["[server]\npackages- = emacs\npackages+ = nano\npackages = vim emacs\n"]);
+        assert_eq!(cfg.get("server", "packages").unwrap(), "vim nano");
+    }
+
+    #[test]
+    fn parse_error_names_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.cfg");
+        fs::write(&path, "[server\npackages = vim\n").unwrap();
+        match Config::load(&[&path]) {
+            Err(Error::Config(e)) => assert!(e.contains("broken.cfg")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path;
+
+use chrono::prelude::*;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{History, Result};
+
+/// An entry of `base.manifest` or `system.manifest`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestEntry {
+    /// `product`, `pattern` or `package`
+    pub kind: String,
+    pub name: String,
+    pub evr: String,
+    pub arch: String,
+    pub buildtime: Option<u64>,
+    /// Repository as `alias:priority`, or `local`
+    pub repo: Option<String>,
+    pub vendor: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Parse a `[kind:]name-evr.arch [buildtime [@repo] [vendor]]`
+    /// line.  The vendor can contain spaces
+    fn from_line(line: &str) -> Option<ManifestEntry> {
+        let mut fields = line.trim().splitn(3, ' ');
+        let nevra = fields.next().filter(|n| !n.is_empty())?;
+        let buildtime = fields.next().and_then(|b| b.parse().ok());
+        let (repo, vendor) = match fields.next() {
+            Some(rest) if rest.starts_with('@') => match rest[1..].split_once(' ') {
+                Some((repo, vendor)) => (Some(repo.to_string()), Some(vendor.to_string())),
+                None => (Some(rest[1..].to_string()), None),
+            },
+            Some(vendor) => (None, Some(vendor.to_string())),
+            None => (None, None),
+        };
+
+        let (kind, nevra) = match nevra.split_once(':') {
+            Some((kind, nevra)) if kind == "product" || kind == "pattern" => (kind, nevra),
+            _ => ("package", nevra),
+        };
+        let (nevr, arch) = nevra.rsplit_once('.')?;
+        let mut nevr = nevr.rsplitn(3, '-');
+        let release = nevr.next()?;
+        let version = nevr.next()?;
+        let name = nevr.next()?;
+
+        Some(ManifestEntry {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            evr: format!("{}-{}", version, release),
+            arch: arch.to_string(),
+            buildtime,
+            repo,
+            vendor,
+        })
+    }
+
+    /// Same version, build and vendor.  A buildtime that is not known
+    /// in one of the entries is not compared
+    fn same_build(&self, other: &ManifestEntry) -> bool {
+        let same_buildtime = match (self.buildtime, other.buildtime) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        self.evr == other.evr && same_buildtime && self.vendor == other.vendor
+    }
+
+    pub fn full_name(&self) -> String {
+        let nevra = format!("{}-{}.{}", self.name, self.evr, self.arch);
+        if self.kind == "package" {
+            nevra
+        } else {
+            format!("{}:{}", self.kind, nevra)
+        }
+    }
+}
+
+/// A package present in both manifests with a different version or
+/// build
+#[derive(Debug, Serialize)]
+pub struct ManifestChange {
+    pub old: ManifestEntry,
+    pub new: ManifestEntry,
+}
+
+/// Differences between two manifests
+#[derive(Default, Debug, Serialize)]
+pub struct ManifestDiff {
+    pub added: Vec<ManifestEntry>,
+    pub removed: Vec<ManifestEntry>,
+    pub upgraded: Vec<ManifestChange>,
+    pub downgraded: Vec<ManifestChange>,
+    /// Same version, but a different build or vendor
+    pub changed: Vec<ManifestChange>,
+}
+
+/// Read a manifest, compressed or not
+pub fn read_manifest(manifest: &path::Path) -> Result<Vec<ManifestEntry>> {
+    let file = fs::File::open(manifest)?;
+    let reader: Box<dyn BufRead> = if manifest.extension().is_some_and(|e| e == "gz") {
+        Box::new(io::BufReader::new(flate2::read::GzDecoder::new(file)))
+    } else {
+        Box::new(io::BufReader::new(file))
+    };
+
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        if let Some(entry) = ManifestEntry::from_line(&line?) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// For a status directory we compare the system manifests
+fn manifest_path(path: &path::Path) -> path::PathBuf {
+    if path.is_dir() {
+        History::new(path).current_dir().join("system.manifest.gz")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+type EntriesByName = BTreeMap<(String, String, String), Vec<ManifestEntry>>;
+
+fn by_name(entries: Vec<ManifestEntry>) -> EntriesByName {
+    let mut by_name = EntriesByName::new();
+    for entry in entries {
+        by_name
+            .entry((entry.kind.clone(), entry.name.clone(), entry.arch.clone()))
+            .or_default()
+            .push(entry);
+    }
+    by_name
+}
+
+impl ManifestDiff {
+    /// Compare two manifests, or the system manifests of two status
+    /// directories
+    pub fn new(old: &path::Path, new: &path::Path) -> Result<ManifestDiff> {
+        let old = read_manifest(&manifest_path(old))?;
+        let new = read_manifest(&manifest_path(new))?;
+        Ok(ManifestDiff::from_entries(old, new))
+    }
+
+    pub fn from_entries(old: Vec<ManifestEntry>, new: Vec<ManifestEntry>) -> ManifestDiff {
+        let pool = libsolv_rs::pool::Pool::new();
+        let old = by_name(old);
+        let mut new = by_name(new);
+
+        let mut diff = ManifestDiff::default();
+        for (key, old_entries) in old {
+            let new_entries = new.remove(&key).unwrap_or_default();
+            diff.compare(&pool, old_entries, new_entries);
+        }
+        diff.added.extend(new.into_values().flatten());
+        diff.added.sort_by_key(|e| e.full_name());
+        diff.removed.sort_by_key(|e| e.full_name());
+
+        diff
+    }
+
+    /// Compare the entries with the same kind, name and arch
+    fn compare(
+        &mut self,
+        pool: &libsolv_rs::pool::Pool,
+        mut old_entries: Vec<ManifestEntry>,
+        mut new_entries: Vec<ManifestEntry>,
+    ) {
+        let evrcmp = |a: &ManifestEntry, b: &ManifestEntry| {
+            pool.evrcmp_str(&a.evr, &b.evr, libsolv_rs::pool::EvrCmpMode::Compare)
+        };
+
+        // Multiversion packages (like kernels) can have many entries,
+        // so first the entries with the same evr are paired
+        old_entries.retain(|o| match new_entries.iter().position(|n| n.evr == o.evr) {
+            Some(i) => {
+                let n = new_entries.remove(i);
+                if !o.same_build(&n) {
+                    self.changed.push(ManifestChange {
+                        old: o.clone(),
+                        new: n,
+                    });
+                }
+                false
+            }
+            None => true,
+        });
+
+        // The rest are paired from the oldest version, and the ones
+        // left in one side are added or removed
+        old_entries.sort_by(evrcmp);
+        new_entries.sort_by(evrcmp);
+        let mut old_entries = old_entries.into_iter();
+        let mut new_entries = new_entries.into_iter();
+        loop {
+            match (old_entries.next(), new_entries.next()) {
+                (Some(old), Some(new)) => {
+                    let order = evrcmp(&old, &new);
+                    let change = ManifestChange { old, new };
+                    match order {
+                        cmp::Ordering::Less => self.upgraded.push(change),
+                        cmp::Ordering::Greater => self.downgraded.push(change),
+                        // Like `1.0-1` and `0:1.0-1`
+                        cmp::Ordering::Equal => self.changed.push(change),
+                    }
+                }
+                (Some(old), None) => self.removed.push(old),
+                (None, Some(new)) => self.added.push(new),
+                (None, None) => break,
+            }
+        }
+    }
+
+    pub fn to_shell(&self) -> String {
+        let names = |entries: &[ManifestEntry]| {
+            entries
+                .iter()
+                .map(|e| e.full_name())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let changes = |changes: &[ManifestChange]| {
+            changes
+                .iter()
+                .map(|c| c.new.full_name())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        [
+            var("ADDED", names(&self.added)),
+            var("REMOVED", names(&self.removed)),
+            var("UPGRADED", changes(&self.upgraded)),
+            var("DOWNGRADED", changes(&self.downgraded)),
+            var("CHANGED", changes(&self.changed)),
+        ]
+        .join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        for (title, entries) in [("Added", &self.added), ("Removed", &self.removed)] {
+            writeln!(text, "{} ({}):", title, entries.len())?;
+            for entry in entries {
+                writeln!(
+                    text,
+                    "  {}{}",
+                    entry.full_name(),
+                    buildtime_to_string(entry.buildtime)
+                )?;
+            }
+        }
+
+        for (title, changes) in [
+            ("Upgraded", &self.upgraded),
+            ("Downgraded", &self.downgraded),
+            ("Changed", &self.changed),
+        ] {
+            writeln!(text, "{} ({}):", title, changes.len())?;
+            for change in changes {
+                writeln!(
+                    text,
+                    "  {}.{}: {}{}{} -> {}{}{}",
+                    change.new.name,
+                    change.new.arch,
+                    change.old.evr,
+                    buildtime_to_string(change.old.buildtime),
+                    vendor_to_string(change.old.vendor.as_deref()),
+                    change.new.evr,
+                    buildtime_to_string(change.new.buildtime),
+                    vendor_to_string(change.new.vendor.as_deref())
+                )?;
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+fn buildtime_to_string(buildtime: Option<u64>) -> String {
+    buildtime
+        .and_then(|b| Utc.timestamp_opt(b as i64, 0).single())
+        .map(|b| format!(" ({})", b.format("%Y-%m-%d")))
+        .unwrap_or_default()
+}
+
+fn vendor_to_string(vendor: Option<&str>) -> String {
+    vendor.map(|v| format!(" [{}]", v)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(line: &str) -> ManifestEntry {
+        ManifestEntry::from_line(line).unwrap()
+    }
+
+    fn names(entries: &[ManifestEntry]) -> Vec<String> {
+        entries.iter().map(|e| e.full_name()).collect()
+    }
+
+    fn changes(changes: &[ManifestChange]) -> Vec<(String, String)> {
+        changes
+            .iter()
+            .map(|c| (c.old.full_name(), c.new.full_name()))
+            .collect()
+    }
+
+    fn compare(old: &[&str], new: &[&str]) -> ManifestDiff {
+        ManifestDiff::from_entries(
+            old.iter().map(|l| entry(l)).collect(),
+            new.iter().map(|l| entry(l)).collect(),
+        )
+    }
+
+    #[test]
+    fn entry_from_line() {
+        let e = entry("kernel-default-6.4.0-150600.23.1.x86_64");
+        assert_eq!(e.kind, "package");
+        assert_eq!(e.name, "kernel-default");
+        assert_eq!(e.evr, "6.4.0-150600.23.1");
+        assert_eq!(e.arch, "x86_64");
+        assert_eq!(e.buildtime, None);
+        assert_eq!(e.repo, None);
+        assert_eq!(e.vendor, None);
+        assert_eq!(e.full_name(), "kernel-default-6.4.0-150600.23.1.x86_64");
+
+        let e = entry("pattern:base-20170319-3.1.x86_64");
+        assert_eq!(e.kind, "pattern");
+        assert_eq!(e.name, "base");
+        assert_eq!(e.full_name(), "pattern:base-20170319-3.1.x86_64");
+
+        let e = entry("perl-URI-1:5.21-1.1.noarch 1700000000");
+        assert_eq!(e.kind, "package");
+        assert_eq!(e.name, "perl-URI");
+        assert_eq!(e.evr, "1:5.21-1.1");
+        assert_eq!(e.buildtime, Some(1700000000));
+    }
+
+    #[test]
+    fn entry_with_repo_and_vendor() {
+        let e =
+            entry("vim-9.0-1.1.x86_64 1700000000 @repo-oss:99 SUSE LLC <https://www.suse.com/>");
+        assert_eq!(e.buildtime, Some(1700000000));
+        assert_eq!(e.repo.as_deref(), Some("repo-oss:99"));
+        assert_eq!(
+            e.vendor.as_deref(),
+            Some("SUSE LLC <https://www.suse.com/>")
+        );
+
+        // The buildtime is not known
+        let e = entry("vim-9.0-1.1.x86_64 - @local");
+        assert_eq!(e.buildtime, None);
+        assert_eq!(e.repo.as_deref(), Some("local"));
+        assert_eq!(e.vendor, None);
+
+        let e = entry("vim-9.0-1.1.x86_64 - @local My Vendor");
+        assert_eq!(e.buildtime, None);
+        assert_eq!(e.vendor.as_deref(), Some("My Vendor"));
+
+        // Older manifests, without the repository
+        let e = entry("vim-9.0-1.1.x86_64 1700000000 openSUSE Build Service");
+        assert_eq!(e.repo, None);
+        assert_eq!(e.vendor.as_deref(), Some("openSUSE Build Service"));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(ManifestEntry::from_line("").is_none());
+        assert!(ManifestEntry::from_line("   ").is_none());
+        assert!(ManifestEntry::from_line("noarch").is_none());
+        assert!(ManifestEntry::from_line("vim.x86_64").is_none());
+    }
+
+    #[test]
+    fn added_removed_and_versions() {
+        let diff = compare(
+            &[
+                "vim-9.0-1.1.x86_64",
+                "emacs-29.1-1.1.x86_64",
+                "bash-5.2-2.1.x86_64",
+            ],
+            &[
+                "vim-9.1-1.1.x86_64",
+                "bash-5.2-1.1.x86_64",
+                "zsh-5.9-1.1.x86_64",
+                "pattern:base-20170319-3.1.x86_64",
+            ],
+        );
+        assert_eq!(
+            names(&diff.added),
+            vec!["pattern:base-20170319-3.1.x86_64", "zsh-5.9-1.1.x86_64"]
+        );
+        assert_eq!(names(&diff.removed), vec!["emacs-29.1-1.1.x86_64"]);
+        assert_eq!(
+            changes(&diff.upgraded),
+            vec![(
+                "vim-9.0-1.1.x86_64".to_string(),
+                "vim-9.1-1.1.x86_64".to_string()
+            )]
+        );
+        assert_eq!(
+            changes(&diff.downgraded),
+            vec![(
+                "bash-5.2-2.1.x86_64".to_string(),
+                "bash-5.2-1.1.x86_64".to_string()
+            )]
+        );
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn different_arch_is_another_package() {
+        let diff = compare(&["glibc-2.38-1.1.i586"], &["glibc-2.38-1.1.x86_64"]);
+        assert_eq!(names(&diff.added), vec!["glibc-2.38-1.1.x86_64"]);
+        assert_eq!(names(&diff.removed), vec!["glibc-2.38-1.1.i586"]);
+        assert!(diff.upgraded.is_empty());
+    }
+
+    #[test]
+    fn multiversion_pairing() {
+        let diff = compare(
+            &[
+                "kernel-default-6.2-1.1.x86_64",
+                "kernel-default-6.1-1.1.x86_64",
+            ],
+            &[
+                "kernel-default-6.4-1.1.x86_64",
+                "kernel-default-6.2-1.1.x86_64",
+                "kernel-default-6.3-1.1.x86_64",
+            ],
+        );
+        assert_eq!(
+            changes(&diff.upgraded),
+            vec![(
+                "kernel-default-6.1-1.1.x86_64".to_string(),
+                "kernel-default-6.3-1.1.x86_64".to_string()
+            )]
+        );
+        assert_eq!(names(&diff.added), vec!["kernel-default-6.4-1.1.x86_64"]);
+        assert!(diff.removed.is_empty());
+        assert!(diff.changed.is_empty());
+
+        let diff = compare(
+            &[
+                "kernel-default-6.1-1.1.x86_64",
+                "kernel-default-6.2-1.1.x86_64",
+                "kernel-default-6.3-1.1.x86_64",
+            ],
+            &["kernel-default-6.4-1.1.x86_64"],
+        );
+        assert_eq!(
+            changes(&diff.upgraded),
+            vec![(
+                "kernel-default-6.1-1.1.x86_64".to_string(),
+                "kernel-default-6.4-1.1.x86_64".to_string()
+            )]
+        );
+        assert_eq!(
+            names(&diff.removed),
+            vec![
+                "kernel-default-6.2-1.1.x86_64",
+                "kernel-default-6.3-1.1.x86_64"
+            ]
+        );
+    }
+
+    #[test]
+    fn same_version_different_build() {
+        let diff = compare(
+            &[
+                "vim-9.0-1.1.x86_64 1700000000 @repo-oss:99 SUSE LLC",
+                "emacs-29.1-1.1.x86_64 1700000000 @repo-oss:99 SUSE LLC",
+                "bash-5.2-1.1.x86_64 1700000000 @repo-oss:99 SUSE LLC",
+                "zsh-5.9-1.1.x86_64 - @local",
+                "foo-1.0-1.x86_64",
+            ],
+            &[
+                "vim-9.0-1.1.x86_64 1800000000 @repo-oss:99 SUSE LLC",
+                "emacs-29.1-1.1.x86_64 1700000000 @local",
+                "bash-5.2-1.1.x86_64 1700000000 @repo-update:99 SUSE LLC",
+                "zsh-5.9-1.1.x86_64 1700000000 @local",
+                "foo-0:1.0-1.x86_64",
+            ],
+        );
+        assert_eq!(
+            changes(&diff.changed),
+            vec![
+                (
+                    "emacs-29.1-1.1.x86_64".to_string(),
+                    "emacs-29.1-1.1.x86_64".to_string()
+                ),
+                (
+                    "foo-1.0-1.x86_64".to_string(),
+                    "foo-0:1.0-1.x86_64".to_string()
+                ),
+                (
+                    "vim-9.0-1.1.x86_64".to_string(),
+                    "vim-9.0-1.1.x86_64".to_string()
+                ),
+            ]
+        );
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.upgraded.is_empty());
+        assert!(diff.downgraded.is_empty());
+    }
+}
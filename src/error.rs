@@ -0,0 +1,146 @@
+use std::ffi;
+use std::fmt;
+use std::io;
+use std::path;
+
+/// Errors reported by the ostatus library
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Error parsing a configuration file
+    Config(String),
+    Xml(xmltree::ParseError),
+    Json(serde_json::Error),
+    Regex(regex::Error),
+    Date(chrono::ParseError),
+    Fmt(fmt::Error),
+    /// Error reading the rpm database
+    Rpm(String),
+    /// A required key is not present in `/etc/os-release`
+    MissingOsRelease(String),
+    /// The base product does not have a name
+    MissingProductName,
+    /// The role is not defined in the configuration
+    RoleNotFound(String),
+    /// The role inherits, directly or not, from itself
+    RoleCycle(String),
+    /// No role is close to the installed system
+    NoRoleDetected,
+    /// The products of the role, the first value, do not include the
+    /// installed base product, the second value
+    ProductMismatch(String, String),
+    /// The solver cannot resolve a role
+    SolverProblems(Vec<libsolv_rs::solver::Problem>),
+    /// There is no solv file for the repository alias
+    MissingSolvCache(String),
+    /// The package is not in any repository
+    MissingBuildtime(String),
+    /// The package of the reference installation is not in any
+    /// repository
+    PackageNotFound(String),
+    /// The directory is not a snapshot of the history
+    InvalidSnapshot(path::PathBuf),
+    /// The architecture of the root cannot be detected
+    MissingArch(path::PathBuf),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "Error parsing the configuration: {}", e),
+            Error::Xml(e) => write!(f, "Error parsing XML: {}", e),
+            Error::Json(e) => write!(f, "Error generating JSON: {}", e),
+            Error::Regex(e) => write!(f, "{}", e),
+            Error::Date(e) => write!(f, "Error parsing the date: {}", e),
+            Error::Fmt(e) => write!(f, "{}", e),
+            Error::Rpm(e) => write!(f, "Error reading the rpm database: {}", e),
+            Error::MissingOsRelease(key) => write!(f, "{} not found in os-release", key),
+            Error::MissingProductName => write!(f, "Product name not found"),
+            Error::RoleNotFound(role) => write!(f, "Role {} not found", role),
+            Error::RoleCycle(roles) => write!(f, "Role inheritance cycle: {}", roles),
+            Error::NoRoleDetected => write!(f, "Role cannot be detected"),
+            Error::ProductMismatch(role, product) => write!(
+                f,
+                "The role {} is not for the installed product {}, it would migrate the product",
+                role, product
+            ),
+            Error::SolverProblems(problems) => {
+                write!(f, "The role cannot be resolved because: ")?;
+                let problems: Vec<_> = problems.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", problems.join("; "))
+            }
+            Error::MissingSolvCache(alias) => {
+                write!(f, "Missing solv file for the repository {}", alias)
+            }
+            Error::MissingBuildtime(package) => {
+                write!(f, "Buildtime not found for {}", package)
+            }
+            Error::PackageNotFound(package) => {
+                write!(f, "Package {} not found in the repositories", package)
+            }
+            Error::InvalidSnapshot(path) => write!(f, "Invalid snapshot {}", path.display()),
+            Error::MissingArch(root) => {
+                write!(f, "Architecture not found for {}", root.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Xml(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Regex(e) => Some(e),
+            Error::Date(e) => Some(e),
+            Error::Fmt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ffi::NulError> for Error {
+    fn from(e: ffi::NulError) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+impl From<xmltree::ParseError> for Error {
+    fn from(e: xmltree::ParseError) -> Self {
+        Error::Xml(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::Regex(e)
+    }
+}
+
+impl From<chrono::ParseError> for Error {
+    fn from(e: chrono::ParseError) -> Self {
+        Error::Date(e)
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Self {
+        Error::Fmt(e)
+    }
+}
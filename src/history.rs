@@ -0,0 +1,345 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::os::unix::fs as unix_fs;
+use std::path;
+
+use chrono::prelude::*;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{staging_dir, Error, Result, Status};
+
+static CURRENT: &str = "current";
+/// Highest id ever given to a snapshot, so the ids of the removed
+/// snapshots are not given again
+static LAST_ID: &str = ".last-id";
+
+/// How many snapshots are kept in the history
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Retention {
+    pub max_count: Option<usize>,
+    pub max_age: Option<chrono::Duration>,
+}
+
+/// A status directory stored in the history
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub id: u32,
+    #[serde(skip)]
+    pub path: path::PathBuf,
+    pub current: bool,
+    pub status: Option<Status>,
+}
+
+/// History of status snapshots, stored as sequence-numbered
+/// subdirectories of the status directory, with a `current` symlink
+/// pointing to the last one
+#[derive(Debug)]
+pub struct History {
+    status_dir: path::PathBuf,
+}
+
+impl History {
+    pub fn new(status_dir: impl AsRef<path::Path>) -> History {
+        History {
+            status_dir: status_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The status directory already contains a history
+    pub fn exists(&self) -> bool {
+        self.status_dir.join(CURRENT).symlink_metadata().is_ok()
+    }
+
+    /// Directory with the last status.  If there is no history, it
+    /// is the status directory itself
+    pub fn current_dir(&self) -> path::PathBuf {
+        let current = self.status_dir.join(CURRENT);
+        if current.is_dir() {
+            current
+        } else {
+            self.status_dir.clone()
+        }
+    }
+
+    fn current_id(&self) -> Option<u32> {
+        fs::read_link(self.status_dir.join(CURRENT))
+            .ok()?
+            .to_str()?
+            .parse()
+            .ok()
+    }
+
+    /// All the snapshots, from the oldest to the newest
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let mut snapshots = Vec::new();
+        if !self.status_dir.exists() {
+            return Ok(snapshots);
+        }
+
+        let current = self.current_id();
+        for entry in fs::read_dir(&self.status_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(id) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+                let path = entry.path();
+                snapshots.push(Snapshot {
+                    id,
+                    current: current == Some(id),
+                    status: Status::from_file(&path.to_string_lossy()).ok(),
+                    path,
+                });
+            }
+        }
+        snapshots.sort_by_key(|s| s.id);
+
+        Ok(snapshots)
+    }
+
+    /// Create a staging directory for a new snapshot
+    pub fn staging(&self) -> Result<tempfile::TempDir> {
+        staging_dir(&self.status_dir)
+    }
+
+    /// Move a complete staging directory to the history, and make it
+    /// the current snapshot
+    pub fn commit(&self, staging: tempfile::TempDir) -> Result<path::PathBuf> {
+        let last = self.snapshots()?.last().map_or(0, |s| s.id);
+        let id = last.max(self.last_id()) + 1;
+        let snapshot = self.status_dir.join(id.to_string());
+        fs::rename(staging.path(), &snapshot)?;
+        self.set_last_id(id)?;
+        self.set_current(&snapshot)?;
+        Ok(snapshot)
+    }
+
+    fn last_id(&self) -> u32 {
+        fs::read_to_string(self.status_dir.join(LAST_ID))
+            .ok()
+            .and_then(|id| id.trim().parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn set_last_id(&self, id: u32) -> Result<()> {
+        let last_id_tmp = self.status_dir.join(format!("{}.tmp", LAST_ID));
+        fs::write(&last_id_tmp, format!("{}\n", id))?;
+        fs::rename(last_id_tmp, self.status_dir.join(LAST_ID))?;
+        Ok(())
+    }
+
+    /// Move the status of a flat status directory to the first
+    /// snapshot, so it is not left next to the history.  The snapshot
+    /// is copied in a staging directory and published with
+    /// `set_current`, and only then the flat files are removed, so an
+    /// interrupted migration leaves the flat status untouched.  Returns
+    /// the snapshot, if there was a status to migrate
+    pub fn migrate(&self) -> Result<Option<path::PathBuf>> {
+        if self.exists() || !self.status_dir.exists() {
+            return Ok(None);
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.status_dir)? {
+            let entry = entry?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if entry.file_type()?.is_file() && !hidden {
+                files.push(entry);
+            }
+        }
+        if files.is_empty() {
+            return Ok(None);
+        }
+
+        let staging = self.staging()?;
+        for file in &files {
+            fs::copy(file.path(), staging.path().join(file.file_name()))?;
+        }
+        let snapshot = self.commit(staging)?;
+
+        for file in files {
+            fs::remove_file(file.path())?;
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Point the `current` symlink to the snapshot
+    pub fn set_current(&self, snapshot: &path::Path) -> Result<()> {
+        let name = snapshot
+            .file_name()
+            .ok_or_else(|| Error::InvalidSnapshot(snapshot.to_path_buf()))?;
+        let current_tmp = self.status_dir.join(format!("{}.tmp", CURRENT));
+        if current_tmp.symlink_metadata().is_ok() {
+            fs::remove_file(&current_tmp)?;
+        }
+        unix_fs::symlink(name, &current_tmp)?;
+        fs::rename(current_tmp, self.status_dir.join(CURRENT))?;
+        Ok(())
+    }
+
+    /// Remove the snapshots outside the retention policy.  The
+    /// current snapshot is always kept
+    pub fn prune(&self, retention: &Retention) -> Result<Vec<Snapshot>> {
+        let snapshots = self.snapshots()?;
+        let now = Utc::now();
+
+        let mut removed = Vec::new();
+        let mut count = snapshots.len();
+        for snapshot in snapshots {
+            let too_many = retention.max_count.is_some_and(|max| count > max);
+            let too_old = match (retention.max_age, &snapshot.status) {
+                (Some(max_age), Some(status)) => now.signed_duration_since(status.date) > max_age,
+                _ => false,
+            };
+            if !snapshot.current && (too_many || too_old) {
+                fs::remove_dir_all(&snapshot.path)?;
+                removed.push(snapshot);
+                count -= 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+impl Snapshot {
+    pub fn to_shell(&self) -> String {
+        let status = self.status.as_ref();
+        [
+            var("SNAPSHOT", self.id),
+            var("CURRENT", if self.current { "yes" } else { "no" }),
+            var(
+                "DATE",
+                status.map(|s| s.date.to_string()).unwrap_or_default(),
+            ),
+            var("ROLE", status.map(|s| s.role.as_str()).unwrap_or_default()),
+        ]
+        .join(" ")
+    }
+
+    /// One line summary
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+        write!(
+            text,
+            "{}{:>5}",
+            if self.current { "*" } else { " " },
+            self.id
+        )?;
+        match &self.status {
+            Some(status) => write!(
+                text,
+                "  {}  role: {}  added: {}  removed: {}",
+                status.date.format("%Y-%m-%d %H:%M:%S"),
+                status.role,
+                status.added_patterns.len() + status.added_packages.len(),
+                status.removed_patterns.len() + status.removed_packages.len()
+            )?,
+            None => write!(text, "  (invalid snapshot)")?,
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_flat_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = Status {
+            role: "server".to_string(),
+            ..Status::default()
+        };
+        fs::write(dir.path().join("ostatus"), status.to_shell()).unwrap();
+        fs::write(dir.path().join("system.manifest.gz"), "manifest").unwrap();
+
+        let history = History::new(dir.path());
+        assert!(!history.exists());
+        let snapshot = history.migrate().unwrap().unwrap();
+        assert_eq!(snapshot, dir.path().join("1"));
+        assert!(history.exists());
+        assert_eq!(history.current_dir(), dir.path().join(CURRENT));
+
+        // The flat files are only in the snapshot
+        assert!(!dir.path().join("ostatus").exists());
+        assert!(!dir.path().join("system.manifest.gz").exists());
+        assert_eq!(
+            fs::read_to_string(snapshot.join("system.manifest.gz")).unwrap(),
+            "manifest"
+        );
+
+        let snapshots = history.snapshots().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert!(snapshots[0].current);
+        assert_eq!(snapshots[0].status.as_ref().unwrap().role, "server");
+        assert!(snapshots[0].to_shell().contains("CURRENT='yes'"));
+
+        // Once there is a history there is nothing to migrate
+        assert!(history.migrate().unwrap().is_none());
+    }
+
+    #[test]
+    fn migrate_without_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path());
+        assert!(history.migrate().unwrap().is_none());
+        assert!(!history.exists());
+
+        let history = History::new(dir.path().join("missing"));
+        assert!(history.migrate().unwrap().is_none());
+    }
+
+    #[test]
+    fn commit_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path());
+        for _ in 0..3 {
+            let staging = history.staging().unwrap();
+            fs::write(staging.path().join("ostatus"), Status::default().to_shell()).unwrap();
+            history.commit(staging).unwrap();
+        }
+
+        let snapshots = history.snapshots().unwrap();
+        let ids: Vec<_> = snapshots.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert!(snapshots[2].current);
+        assert!(snapshots[0].to_shell().contains("CURRENT='no'"));
+
+        let retention = Retention {
+            max_count: Some(1),
+            max_age: None,
+        };
+        let removed: Vec<_> = history
+            .prune(&retention)
+            .unwrap()
+            .iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(removed, vec![1, 2]);
+        assert_eq!(history.current_dir(), dir.path().join(CURRENT));
+        assert!(history.current_dir().join("ostatus").exists());
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path());
+        let commit = || {
+            let staging = history.staging().unwrap();
+            fs::write(staging.path().join("ostatus"), Status::default().to_shell()).unwrap();
+            history.commit(staging).unwrap()
+        };
+        commit();
+        let last = commit();
+        assert_eq!(last, dir.path().join("2"));
+
+        fs::remove_dir_all(&last).unwrap();
+        history.set_current(&dir.path().join("1")).unwrap();
+        assert_eq!(commit(), dir.path().join("3"));
+        let ids: Vec<_> = history.snapshots().unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+}
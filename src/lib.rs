@@ -1,33 +1,66 @@
-use std::cmp::Eq;
 use std::collections::{HashMap, HashSet};
-use std::fmt::Write as _;
+use std::ffi;
 use std::fs;
-use std::hash::Hash;
 use std::io::{self, BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
 use std::path;
 use std::str;
-use std::sync::Once;
 use std::time;
 
 use chrono::prelude::*;
 use configparser::ini;
+use libsolv_rs::job::Job;
+use libsolv_rs::pool::Pool;
+use libsolv_rs::repo::{RepoFlags, RepoMut};
+use libsolv_rs::selection::Selection;
+use libsolv_rs::solver::{Solver, SolverFlag};
 use regex::Regex;
 use sha2::{Digest, Sha256};
 
-static CONFIGURE: Once = Once::new();
+mod check;
+mod config;
+mod diff;
+mod error;
+mod history;
+mod outdated;
+mod patches;
+mod plan;
+mod remediate;
+mod score;
+mod shell;
+mod status;
+
+pub use check::{ConfigCheck, ConfigIssue, Severity};
+pub use config::{Config, ConfigValue};
+pub use diff::{ManifestChange, ManifestDiff, ManifestEntry};
+pub use error::{Error, Result};
+pub use history::{History, Retention, Snapshot};
+pub use outdated::{Outdated, OutdatedPackage};
+pub use patches::{NeededPatch, Patches};
+pub use plan::{ArchChange, PackageChange, Plan, VendorChange};
+pub use remediate::{is_transactional, Remediation};
+pub use score::{RoleRanking, RoleScore};
+pub use status::Status;
 
 pub static STATUS_DIR: &str = "/usr/lib/sysimage/ostatus";
 
 static CONFIG_DIR_SYS: &str = "/usr/etc/ostatus";
 static CONFIG_DIR: &str = "/etc/ostatus";
 static CONFIG: &str = "ostatus.cfg";
+static SYSTEM_SECTION: &str = "system";
+static LOCAL_REPO: &str = "local";
+/// Prefix of the packages of a role that are anything providing the
+/// name, like `provides:kernel`
+static PROVIDES_PREFIX: &str = "provides:";
 
 pub type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
-pub type GenericResult<T> = Result<T, GenericError>;
+pub type GenericResult<T> = std::result::Result<T, GenericError>;
 
 /// An installation of reference
 #[derive(Default, Debug)]
 pub struct ReferenceInstallation {
+    pub products: Vec<String>,
     pub patterns: Vec<String>,
     pub packages: Vec<String>,
     pub patterns_opt: Vec<String>,
@@ -35,32 +68,51 @@ pub struct ReferenceInstallation {
 }
 
 impl ReferenceInstallation {
-    fn from_ini(cfg: &ini::Ini, section: &str) -> ReferenceInstallation {
-        ReferenceInstallation {
-            patterns: cfg
-                .get(section, "patterns")
-                .unwrap_or_default()
-                .split_whitespace()
-                .map(|s| s.to_string())
-                .collect(),
-            packages: cfg
-                .get(section, "packages")
+    /// Read the role from its section.  The lists that are not set
+    /// are inherited from the parents, and then extended with the
+    /// `key+` values and reduced with the `key-` ones
+    fn from_ini(
+        cfg: &Config,
+        section: &str,
+        parents: &[&ReferenceInstallation],
+    ) -> ReferenceInstallation {
+        let split = |value: Option<String>| -> Vec<String> {
+            value
                 .unwrap_or_default()
                 .split_whitespace()
                 .map(|s| s.to_string())
-                .collect(),
-            patterns_opt: cfg
-                .get(section, "patterns_opt")
-                .unwrap_or_default()
-                .split_whitespace()
-                .map(|s| s.to_string())
-                .collect(),
-            packages_opt: cfg
-                .get(section, "packages_opt")
-                .unwrap_or_default()
-                .split_whitespace()
-                .map(|s| s.to_string())
-                .collect(),
+                .collect()
+        };
+
+        let list = |key: &str, field: fn(&ReferenceInstallation) -> &Vec<String>| {
+            let mut values = match cfg.get(section, key) {
+                Some(value) => split(Some(value)),
+                None => {
+                    let mut inherited = Vec::new();
+                    for value in parents.iter().flat_map(|p| field(p)) {
+                        if !inherited.contains(value) {
+                            inherited.push(value.clone());
+                        }
+                    }
+                    inherited
+                }
+            };
+            for value in split(cfg.get(section, &format!("{}+", key))) {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            let removed = split(cfg.get(section, &format!("{}-", key)));
+            values.retain(|v| !removed.contains(v));
+            values
+        };
+
+        ReferenceInstallation {
+            products: list("products", |r| &r.products),
+            patterns: list("patterns", |r| &r.patterns),
+            packages: list("packages", |r| &r.packages),
+            patterns_opt: list("patterns_opt", |r| &r.patterns_opt),
+            packages_opt: list("packages_opt", |r| &r.packages_opt),
         }
     }
 }
@@ -69,56 +121,83 @@ impl ReferenceInstallation {
 pub struct Roles(pub HashMap<String, ReferenceInstallation>);
 
 impl Roles {
-    pub fn from_config(paths: &[impl AsRef<path::Path>]) -> GenericResult<Roles> {
-        let mut all_cfgs = String::new();
-
-        for path in paths {
-            all_cfgs.push_str(&fs::read_to_string(path)?);
-        }
-
-        let mut cfg = ini::Ini::new();
-        cfg.read(all_cfgs)?;
-
+    /// Roles of the configuration, with the values that they inherit
+    pub fn from_config(cfg: &Config) -> Result<Roles> {
         let mut roles = Roles::default();
         roles.0.insert(
             "default".to_string(),
-            ReferenceInstallation::from_ini(&cfg, "default"),
+            ReferenceInstallation::from_ini(cfg, "default", &[]),
         );
 
         for section in cfg.sections() {
-            if section != "default" {
-                roles.0.insert(
-                    section.clone(),
-                    ReferenceInstallation::from_ini(&cfg, &section),
-                );
+            if section != SYSTEM_SECTION {
+                roles.resolve(cfg, &section, &mut Vec::new())?;
             }
         }
+        // The default role is only the base of the other roles
+        roles.0.remove("default");
 
         Ok(roles)
     }
 
-    pub fn apply_default(&mut self) {
-        if let Some(default) = self.0.remove("default") {
-            for installation in self.0.values_mut() {
-                if installation.patterns.is_empty() {
-                    installation.patterns = default.patterns.clone();
-                }
-                if installation.packages.is_empty() {
-                    installation.packages = default.packages.clone();
-                }
-                if installation.patterns_opt.is_empty() {
-                    installation.patterns_opt = default.patterns_opt.clone();
-                }
-                if installation.packages_opt.is_empty() {
-                    installation.packages_opt = default.packages_opt.clone();
-                }
-            }
+    /// Read the role and, before it, the roles that it inherits.  A
+    /// role without `inherits` inherits from `default`
+    fn resolve(&mut self, cfg: &Config, role: &str, visiting: &mut Vec<String>) -> Result<()> {
+        if self.0.contains_key(role) {
+            return Ok(());
+        }
+        if visiting.iter().any(|r| r == role) {
+            visiting.push(role.to_string());
+            return Err(Error::RoleCycle(visiting.join(" -> ")));
+        }
+        if role == SYSTEM_SECTION || !cfg.sections().iter().any(|s| s == role) {
+            return Err(Error::RoleNotFound(role.to_string()));
+        }
+
+        let parents: Vec<String> = match cfg.get(role, "inherits") {
+            Some(inherits) => inherits.split_whitespace().map(|s| s.to_string()).collect(),
+            None => vec!["default".to_string()],
+        };
+        visiting.push(role.to_string());
+        for parent in &parents {
+            self.resolve(cfg, parent, visiting)?;
+        }
+        visiting.pop();
+
+        let parents: Vec<_> = parents.iter().map(|p| &self.0[p]).collect();
+        let installation = ReferenceInstallation::from_ini(cfg, role, &parents);
+        self.0.insert(role.to_string(), installation);
+
+        Ok(())
+    }
+}
+
+/// Settings of the `[system]` section, that are not part of any role
+#[derive(Default, Debug)]
+pub struct SystemConfig {
+    /// Role of the system, that replaces the detected one
+    pub role: Option<String>,
+}
+
+impl SystemConfig {
+    pub fn from_config(cfg: &Config) -> SystemConfig {
+        SystemConfig {
+            role: cfg.get(SYSTEM_SECTION, "role").filter(|r| !r.is_empty()),
         }
     }
 }
 
-pub fn find_configs() -> GenericResult<Vec<path::PathBuf>> {
-    let release = OsRelease::new()?;
+/// Path of `path` inside the alternate root directory
+fn rooted(root: &path::Path, path: &str) -> path::PathBuf {
+    root.join(path.trim_start_matches('/'))
+}
+
+/// Configuration files, from the lowest to the highest precedence.
+/// The files in `/etc` take precedence over the ones in `/usr/etc`,
+/// and the drop-ins in `ostatus.cfg.d` over the main files of the same
+/// directory
+pub fn find_configs(root: &path::Path) -> Result<Vec<path::PathBuf>> {
+    let release = OsRelease::new(root)?;
 
     let mut filenames = Vec::new();
     filenames.push(format!("{}.cfg", release.id));
@@ -128,24 +207,36 @@ pub fn find_configs() -> GenericResult<Vec<path::PathBuf>> {
     let mut configs = Vec::new();
     for dir in [CONFIG_DIR_SYS, CONFIG_DIR] {
         for filename in &filenames {
-            let config: path::PathBuf = [dir, filename].iter().collect();
+            let config = rooted(root, dir).join(filename);
             if config.exists() {
                 configs.push(config);
             }
         }
+
+        let dropins_dir = rooted(root, dir).join(format!("{}.d", CONFIG));
+        if dropins_dir.is_dir() {
+            let mut dropins = Vec::new();
+            for entry in fs::read_dir(dropins_dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|e| e == "cfg") && path.is_file() {
+                    dropins.push(path);
+                }
+            }
+            dropins.sort();
+            configs.extend(dropins);
+        }
     }
 
     Ok(configs)
 }
 
-fn baseproduct() -> GenericResult<String> {
-    let baseproduct = fs::File::open("/etc/products.d/baseproduct")?;
+fn baseproduct(root: &path::Path) -> Result<String> {
+    let baseproduct = fs::File::open(rooted(root, "/etc/products.d/baseproduct"))?;
     let product = xmltree::Element::parse(baseproduct)?;
     let baseproduct = product
         .get_child("name")
-        .expect("Product name not found")
-        .get_text()
-        .unwrap()
+        .and_then(|name| name.get_text())
+        .ok_or(Error::MissingProductName)?
         .into_owned();
     Ok(baseproduct)
 }
@@ -156,20 +247,20 @@ struct ZypperRepo {
     pub priority: i64,
 }
 
-fn repo_alias() -> GenericResult<Vec<ZypperRepo>> {
+fn repo_alias(root: &path::Path) -> Result<Vec<ZypperRepo>> {
     let mut repos = Vec::new();
     let mut urls = HashSet::new();
 
-    let files = fs::read_dir("/etc/zypp/repos.d")?;
+    let files = fs::read_dir(rooted(root, "/etc/zypp/repos.d"))?;
     for repo_fn in files {
         let path = repo_fn?.path();
         if let Some(extension) = path.extension() {
             if extension == "repo" {
                 let mut config = ini::Ini::new();
-                config.load(path)?;
+                config.load(path).map_err(Error::Config)?;
                 for alias in config.sections() {
                     let solv = format!("/var/cache/zypp/solv/{}/solv", alias);
-                    if !path::Path::new(&solv).exists() {
+                    if !rooted(root, &solv).exists() {
                         continue;
                     }
                     let priority = config
@@ -208,27 +299,24 @@ struct OsRelease {
 }
 
 impl OsRelease {
-    pub fn new() -> GenericResult<OsRelease> {
+    pub fn new(root: &path::Path) -> Result<OsRelease> {
         let mut config = ini::Ini::new();
-        config.load("/etc/os-release")?;
+        config
+            .load(rooted(root, "/etc/os-release"))
+            .map_err(Error::Config)?;
+
+        let get = |key: &str| {
+            config
+                .get("default", key)
+                .map(|value| value.replace('"', ""))
+                .ok_or_else(|| Error::MissingOsRelease(key.to_string()))
+        };
 
         Ok(OsRelease {
-            _name: config
-                .get("default", "NAME")
-                .expect("NAME not found in os-release")
-                .replace('"', ""),
-            id: config
-                .get("default", "ID")
-                .expect("ID not found in os-release")
-                .replace('"', ""),
-            version_id: config
-                .get("default", "VERSION_ID")
-                .expect("VERSION_ID not found in os-release")
-                .replace('"', ""),
-            _pretty_name: config
-                .get("default", "PRETTY_NAME")
-                .expect("PRETTY_NAME not found in os-release")
-                .replace('"', ""),
+            _name: get("NAME")?,
+            id: get("ID")?,
+            version_id: get("VERSION_ID")?,
+            _pretty_name: get("PRETTY_NAME")?,
         })
     }
 }
@@ -241,9 +329,10 @@ struct Installation {
 }
 
 impl Installation {
-    fn autoinstalled() -> GenericResult<HashSet<String>> {
+    fn autoinstalled(root: &path::Path) -> Result<HashSet<String>> {
         let mut autoinst = HashSet::new();
-        let autoinst_file = io::BufReader::new(fs::File::open("/var/lib/zypp/AutoInstalled")?);
+        let autoinst_file =
+            io::BufReader::new(fs::File::open(rooted(root, "/var/lib/zypp/AutoInstalled"))?);
         for line in autoinst_file.lines().flatten() {
             if line.starts_with('#') {
                 continue;
@@ -253,176 +342,248 @@ impl Installation {
         Ok(autoinst)
     }
 
-    fn from_system_no_autoinstalled() -> GenericResult<Installation> {
-        let autoinst = Installation::autoinstalled()?;
+    /// Add the product, pattern or package
+    fn push(&mut self, mut package: libsolv_rs::pool::Package) {
+        let type_name: Vec<&str> = package.name.split(':').collect();
+        let (kind, name) = match type_name[..] {
+            ["product", name] => ("product", name.to_owned()),
+            ["pattern", name] => ("pattern", name.to_owned()),
+            [name] => ("package", name.to_owned()),
+            _ => return,
+        };
+        package.name = name;
+        match kind {
+            "product" => self.products.push(package),
+            "pattern" => self.patterns.push(package),
+            _ => self.packages.push(package),
+        };
+    }
 
-        let mut products = Vec::new();
-        let mut patterns = Vec::new();
-        let mut packages = Vec::new();
+    /// Installed system, read from the rpm database.  The elements in
+    /// `exclude` are skipped
+    fn from_rpmdb(root: &path::Path, exclude: &HashSet<String>) -> Result<Installation> {
+        let mut pool = Pool::new();
+        pool.set_rootdir(&root.to_string_lossy());
+        add_rpmdb_repo(&mut pool)?;
+        Ok(Installation::from_installed(&pool, exclude))
+    }
 
-        let system = io::BufReader::new(fs::File::open("/var/cache/zypp/solv/@System/solv.idx")?);
-        for line in system.lines().flatten() {
-            let element: Vec<&str> = line.split_whitespace().collect();
-            if let [name, version, arch] = element[..] {
-                if autoinst.contains(name) {
-                    continue;
-                }
-                let version = version.to_owned();
-                let arch = arch.to_owned();
-                let type_name: Vec<&str> = name.split(':').collect();
-                match type_name[..] {
-                    ["product", name] => products.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    ["pattern", name] => patterns.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    [name] => packages.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    _ => (),
-                };
+    /// Installed system of a pool that already has it loaded.  The
+    /// elements in `exclude` are skipped
+    fn from_installed(pool: &Pool, exclude: &HashSet<String>) -> Installation {
+        let mut installation = Installation {
+            products: Vec::new(),
+            patterns: Vec::new(),
+            packages: Vec::new(),
+        };
+
+        for solvable in pool.installed().into_iter().flat_map(|r| r.solvables()) {
+            if !exclude.contains(&solvable.name()) {
+                installation.push(libsolv_rs::pool::Package::from_solvable(&solvable));
             }
         }
 
-        Ok(Installation {
-            products,
-            patterns,
-            packages,
-        })
+        installation
     }
 
-    fn from_system() -> GenericResult<Installation> {
-        let mut products = Vec::new();
-        let mut patterns = Vec::new();
-        let mut packages = Vec::new();
-
-        let system = io::BufReader::new(fs::File::open("/var/cache/zypp/solv/@System/solv.idx")?);
-        for line in system.lines().flatten() {
-            let element: Vec<&str> = line.split_whitespace().collect();
-            if let [name, version, arch] = element[..] {
-                let version = version.to_owned();
-                let arch = arch.to_owned();
-                let type_name: Vec<&str> = name.split(':').collect();
-                match type_name[..] {
-                    ["product", name] => products.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    ["pattern", name] => patterns.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    [name] => packages.push(libsolv_rs::pool::Package {
-                        name: name.to_owned(),
-                        version,
-                        arch,
-                    }),
-                    _ => (),
-                };
-            }
-        }
+    fn from_system(root: &path::Path) -> Result<Installation> {
+        Installation::from_rpmdb(root, &HashSet::new())
+    }
 
-        Ok(Installation {
-            products,
-            patterns,
-            packages,
-        })
+    /// Canonical names (`[kind:]name-evr.arch`) of all the elements
+    fn canonical_names(&self) -> Vec<String> {
+        self.names(|p| p.full_name())
     }
 
-    fn from_role(role: &str, roles: &Roles) -> GenericResult<Installation> {
-        let repo_alias = repo_alias()?;
-        // TODO The product information should be part of Roles
-        let products = vec![baseproduct()?];
-        let ref_installation = roles.0.get(role).expect("Role not found");
-        let test_case = testcase(
-            &repo_alias,
-            &products,
-            &ref_installation.patterns,
-            &ref_installation.packages,
-        )?;
+    /// Names with the arch (`[kind:]name.arch`) of all the elements
+    fn name_archs(&self) -> Vec<String> {
+        self.names(|p| format!("{}.{}", p.name, p.arch))
+    }
 
-        let mut pool = libsolv_rs::pool::Pool::new();
-        let installables = pool.testsolv(&test_case);
+    fn names(&self, name: impl Fn(&libsolv_rs::pool::Package) -> String) -> Vec<String> {
+        let products = self.products.iter().map(|p| format!("product:{}", name(p)));
+        let patterns = self.patterns.iter().map(|p| format!("pattern:{}", name(p)));
+        let packages = self.packages.iter().map(&name);
+        products.chain(patterns).chain(packages).collect()
+    }
 
-        let mut products = Vec::new();
-        let mut patterns = Vec::new();
-        let mut packages = Vec::new();
+    /// Reference installation of the role, resolved from scratch with
+    /// the repositories of the pool.  The installed system of the
+    /// pool is hidden from the solver meanwhile
+    fn from_role(
+        pool: &mut Pool,
+        root: &path::Path,
+        role: &str,
+        roles: &Roles,
+    ) -> Result<Installation> {
+        let baseproduct = baseproduct(root)?;
+
+        without_installed(pool, |pool| {
+            let jobs = role_jobs(pool, &baseproduct, role, roles)?;
+            let mut solver = zypper_solver(pool, root)?;
+            Installation::from_jobs(&mut solver, &jobs)
+        })
+    }
+
+    /// Installation that the solver resolves for the jobs.  The solver
+    /// can be reused for other jobs
+    fn from_jobs(solver: &mut Solver, jobs: &[Job]) -> Result<Installation> {
+        if solver.solve_jobs(jobs) > 0 {
+            return Err(Error::SolverProblems(solver.problems()));
+        }
+        let installables = solver.transaction().installs();
 
+        let mut installation = Installation {
+            products: Vec::new(),
+            patterns: Vec::new(),
+            packages: Vec::new(),
+        };
         for installable in installables.into_iter() {
-            let type_name: Vec<&str> = installable.name.split(':').collect();
-            let version = installable.version;
-            let arch = installable.arch;
-            match type_name[..] {
-                ["product", name] => products.push(libsolv_rs::pool::Package {
-                    name: name.to_owned(),
-                    version,
-                    arch,
-                }),
-                ["pattern", name] => patterns.push(libsolv_rs::pool::Package {
-                    name: name.to_owned(),
-                    version,
-                    arch,
-                }),
-                [name] => packages.push(libsolv_rs::pool::Package {
-                    name: name.to_owned(),
-                    version,
-                    arch,
-                }),
-                _ => (),
-            };
+            installation.push(installable);
         }
 
-        Ok(Installation {
-            products,
-            patterns,
-            packages,
-        })
+        Ok(installation)
+    }
+}
+
+/// Jobs to install the product and the reference installation of the
+/// role.  The `provides:` packages install anything providing the
+/// name.  The installed base product is always kept, and a role whose
+/// products do not include it is a `ProductMismatch`, as solving it
+/// would migrate the product
+fn role_jobs(pool: &mut Pool, baseproduct: &str, role: &str, roles: &Roles) -> Result<Vec<Job>> {
+    let ref_installation = roles
+        .0
+        .get(role)
+        .ok_or_else(|| Error::RoleNotFound(role.to_string()))?;
+
+    let products = &ref_installation.products;
+    if !products.is_empty() && !products.iter().any(|p| p == baseproduct) {
+        return Err(Error::ProductMismatch(
+            role.to_string(),
+            baseproduct.to_string(),
+        ));
+    }
+    let mut jobs = vec![Job::install_product(pool, baseproduct)];
+    for pattern in &ref_installation.patterns {
+        jobs.push(Job::install_pattern(pool, pattern));
     }
+    for package in &ref_installation.packages {
+        match package.strip_prefix(PROVIDES_PREFIX) {
+            Some(dep) => jobs.push(Job::install().provides(pool, dep)),
+            None => jobs.push(Job::install_name(pool, package)),
+        }
+    }
+
+    Ok(jobs)
 }
 
-fn jaccard<T>(set1: &HashSet<T>, set2: &HashSet<T>) -> f64
-where
-    T: Eq + Hash,
-{
-    let union = set1.union(set2);
-    let intersection = set1.intersection(set2);
-    intersection.count() as f64 / union.count() as f64
+/// Pool with the solv cache of all the repositories and the installed
+/// system, ready to solve.  It is shared by the reports of the
+/// installed system
+fn system_pool(root: &path::Path) -> Result<Pool> {
+    let mut pool = Pool::new();
+    pool.set_rootdir(&root.to_string_lossy());
+    // The installed system is loaded first, as it can tell the
+    // architecture of the repositories
+    add_rpmdb_repo(&mut pool)?;
+    pool.set_arch(&root_arch(&pool, root)?);
+    for repo in repo_alias(root)? {
+        // In zypper the lower priority wins, and in libsolv the higher
+        add_solv_repo(&mut pool, root, &repo.alias)?.set_priority(-(repo.priority as i32));
+    }
+    pool.add_file_provides();
+    pool.create_whatprovides();
+    Ok(pool)
 }
 
-fn find_closer_role(roles: &Roles, installation: &Installation) -> GenericResult<Option<String>> {
-    let installed_patterns_set: HashSet<_> =
-        installation.patterns.iter().map(|p| &p.name).collect();
+/// Run `f` with the installed repo of the pool disabled, as if the
+/// pool had only the repositories.  The whatprovides index is created
+/// again before and after
+fn without_installed<T>(pool: &mut Pool, f: impl FnOnce(&mut Pool) -> T) -> T {
+    let installed = pool.installed().map(|r| r.id());
+    if let Some(mut repo) = installed.and_then(|id| pool.repo_mut(id)) {
+        repo.set_disabled(true);
+    }
+    pool.unset_installed();
+    pool.create_whatprovides();
 
-    let mut best_role = None;
-    let mut best_index = 0.0;
-    for (role, ref_installation) in &roles.0 {
-        let patterns: HashSet<_> = ref_installation.patterns.iter().collect();
-        let index = jaccard(&installed_patterns_set, &patterns);
-        if index > best_index {
-            best_index = index;
-            best_role = Some(role.clone());
+    let result = f(pool);
+
+    if let Some(mut repo) = installed.and_then(|id| pool.repo_mut(id)) {
+        repo.set_disabled(false);
+        repo.set_installed();
+    }
+    pool.create_whatprovides();
+    result
+}
+
+/// Architecture of the system in the root: the `arch` of zypp.conf,
+/// else the most common one of the installed packages of the pool.
+/// The architecture of the host is used only for `/`
+fn root_arch(pool: &Pool, root: &path::Path) -> Result<String> {
+    if let Some(arch) = ZypperConf::new(root)?.arch {
+        return Ok(arch);
+    }
+
+    let mut archs: HashMap<String, usize> = HashMap::new();
+    for solvable in pool.installed().into_iter().flat_map(|r| r.solvables()) {
+        let arch = solvable.arch();
+        if !["noarch", "src", "nosrc", "(none)", ""].contains(&arch.as_str()) {
+            *archs.entry(arch).or_default() += 1;
         }
     }
+    if let Some((arch, _)) = archs.into_iter().max_by_key(|(_, count)| *count) {
+        return Ok(arch);
+    }
 
-    Ok(best_role)
+    if root == path::Path::new("/") {
+        Ok(std::env::consts::ARCH.to_string())
+    } else {
+        Err(Error::MissingArch(root.to_path_buf()))
+    }
 }
 
-fn apply_optional_for_role(role: &str, roles: &mut Roles, installation: &Installation) {
-    let ref_installation = roles.0.get_mut(role).unwrap();
+/// Solver configured like zypper
+fn zypper_solver<'p>(pool: &'p Pool, root: &path::Path) -> Result<Solver<'p>> {
+    let zypper_conf = ZypperConf::new(root)?;
+    let mut solver = Solver::new(pool);
+    solver.set_flag(SolverFlag::IgnoreRecommended, zypper_conf.only_requires);
+    solver.set_flag(
+        SolverFlag::AllowVendorChange,
+        zypper_conf.allow_vendor_change,
+    );
+    Ok(solver)
+}
 
+/// Add to the role the optional packages and patterns that are
+/// installed.  An optional `provides:` package is installed when any
+/// installed package of the pool provides it
+fn apply_optional_for_role(
+    role: &str,
+    roles: &mut Roles,
+    pool: &Pool,
+    installation: &Installation,
+) -> Result<()> {
+    let ref_installation = roles
+        .0
+        .get_mut(role)
+        .ok_or_else(|| Error::RoleNotFound(role.to_string()))?;
+
+    let installed = pool.installed().map(|r| r.id());
     let installed_packages_set: HashSet<_> =
         installation.packages.iter().map(|p| &p.name).collect();
     for package_opt in &ref_installation.packages_opt {
-        if installed_packages_set.contains(package_opt) {
+        let is_installed = match package_opt.strip_prefix(PROVIDES_PREFIX) {
+            Some(dep) => Selection::provides(pool, dep).is_some_and(|selection| {
+                selection
+                    .solvables(pool)
+                    .iter()
+                    .any(|s| Some(s.repo().id()) == installed)
+            }),
+            None => installed_packages_set.contains(package_opt),
+        };
+        if is_installed {
             ref_installation.packages.push(package_opt.clone());
         }
     }
@@ -434,123 +595,114 @@ fn apply_optional_for_role(role: &str, roles: &mut Roles, installation: &Install
             ref_installation.patterns.push(pattern_opt.clone());
         }
     }
+
+    Ok(())
 }
 
 #[derive(Debug)]
 pub struct ZypperConf {
     pub only_requires: bool,
     pub allow_vendor_change: bool,
+    /// Architecture forced in the configuration
+    pub arch: Option<String>,
 }
 
 impl ZypperConf {
-    pub fn new() -> GenericResult<ZypperConf> {
-        let conf = fs::read_to_string("/etc/zypp/zypp.conf")?;
+    pub fn new(root: &path::Path) -> Result<ZypperConf> {
+        let conf = fs::read_to_string(rooted(root, "/etc/zypp/zypp.conf"))?;
         let re_only_requires = Regex::new(r"(?m)^solver.onlyRequires\s*=\s*true")?;
         let re_allow_vendor_change = Regex::new(r"(?m)^solver.allowVendorChange\s*=\s*true")?;
+        let re_arch = Regex::new(r"(?m)^arch\s*=\s*(\S+)")?;
 
         Ok(ZypperConf {
             only_requires: re_only_requires.is_match(&conf),
             allow_vendor_change: re_allow_vendor_change.is_match(&conf),
+            arch: re_arch.captures(&conf).map(|c| c[1].to_string()),
         })
     }
 }
 
-fn testcase(
-    repo_alias: &[ZypperRepo],
-    products: &[String],
-    patterns: &[String],
-    packages: &[String],
-) -> GenericResult<String> {
-    let mut repos = "".to_string();
-    for repo in repo_alias {
-        writeln!(
-            repos,
-            "repo {alias} {prio} solv /var/cache/zypp/solv/{alias}/solv",
-            alias = repo.alias,
-            prio = repo.priority
-        )?;
-    }
-
-    let mut flags = Vec::new();
-    let zypper_conf = ZypperConf::new()?;
-    if zypper_conf.only_requires {
-        flags.push("ignorerecommended");
+/// Load the installed system from the rpm database, and mark it as
+/// installed in the pool
+fn add_rpmdb_repo(pool: &mut Pool) -> Result<RepoMut<'_>> {
+    let mut repo = RepoMut::new(pool, "@System");
+    if repo.add_rpmdb(RepoFlags::USE_ROOTDIR) != 0 {
+        return Err(Error::Rpm(repo.pool().errstr()));
     }
-    if zypper_conf.allow_vendor_change {
-        flags.push("allowvendorchange");
-    }
-    if !flags.is_empty() {
-        flags.insert(0, "solverflags");
-        flags.push("\n");
-    }
-    let flags = flags.join(" ");
-
-    let mut jobs = Vec::new();
-    for product in products {
-        jobs.push(format!("job install name product:{}", product));
-    }
-    for pattern in patterns {
-        jobs.push(format!("job install name pattern:{}", pattern));
-    }
-    for package in packages {
-        jobs.push(format!("job install name {}", package));
-    }
-    let jobs = jobs.join("\n");
-
-    let testcase = format!(
-        "system {arch} rpm\n\n{repos}\n{flags}{jobs}",
-        arch = std::env::consts::ARCH,
-        repos = repos,
-        flags = flags,
-        jobs = jobs
-    );
-
-    Ok(testcase)
+    repo.add_autopattern();
+    repo.set_installed();
+    Ok(repo)
 }
 
-fn buildtime_from_repos(repos: &[String]) -> HashMap<String, u64> {
-    let mut buildtimes = HashMap::new();
-
-    let mut pool = libsolv_rs::pool::Pool::new();
-    let mut repo = libsolv_rs::repo::Repo::new(&mut pool, "local solv repos");
-    for alias in repos {
-        repo.add_solv(
-            &format!("/var/cache/zypp/solv/{}/solv", alias),
-            libsolv_rs::repo::RepoFlags::empty(),
-        );
+/// Load the solv cache of the repository
+fn add_solv_repo<'p>(pool: &'p mut Pool, root: &path::Path, alias: &str) -> Result<RepoMut<'p>> {
+    let solv = format!("/var/cache/zypp/solv/{}/solv", alias);
+    let mut repo = RepoMut::new(pool, alias);
+    if !rooted(root, &solv).exists() || repo.add_solv(&solv, RepoFlags::USE_ROOTDIR) != 0 {
+        return Err(Error::MissingSolvCache(alias.to_string()));
     }
-    for repoid in 1..pool.nrepos() {
-        let r = pool.repo(repoid).unwrap();
-        for solvableid in r.start()..r.end() {
-            let mut solvable = pool.solvable(solvableid).unwrap();
-            buildtimes.insert(solvable.nevra(), solvable.buildtime());
-        }
-    }
-
-    buildtimes
+    Ok(repo)
 }
 
-fn configure() {
-    CONFIGURE.call_once(|| {
-        librpm::config::read_file(None).unwrap();
-    });
+/// Package available in a repository
+struct RepoPackage {
+    buildtime: u64,
+    alias: String,
+    priority: i64,
 }
 
-fn buildtime_from_system() -> HashMap<String, time::SystemTime> {
-    let mut buildtimes = HashMap::new();
-
-    configure();
-    for p in librpm::db::installed_packages() {
-        buildtimes.insert(p.nevra(), p.buildtime());
+/// Packages of the repositories of the pool by NEVRA.  When many
+/// repositories have the same NEVRA, the one with the best priority is
+/// kept
+fn packages_from_repos(pool: &Pool, repos: &[ZypperRepo]) -> HashMap<String, RepoPackage> {
+    let mut packages: HashMap<String, RepoPackage> = HashMap::new();
+
+    let installed = pool.installed().map(|r| r.id());
+    // The repositories are loaded sorted by priority, the first one
+    // wins
+    for solvable in pool.solvables() {
+        if Some(solvable.repo().id()) == installed {
+            continue;
+        }
+        let alias = solvable.repo().name();
+        let priority = repos
+            .iter()
+            .find(|r| r.alias == alias)
+            .map(|r| r.priority)
+            .unwrap_or_default();
+        packages
+            .entry(solvable.nevra())
+            .or_insert_with(|| RepoPackage {
+                buildtime: solvable.buildtime(),
+                alias,
+                priority,
+            });
     }
 
-    buildtimes
+    packages
 }
 
-fn base_manifest(installation: &Installation, status_dir: &str) -> GenericResult<()> {
-    let repo_alias: Vec<String> = repo_alias()?.into_iter().map(|r| r.alias).collect();
-    let buildtimes = buildtime_from_repos(&repo_alias);
+/// Buildtime of the installed packages, by NEVRA, from the installed
+/// system of the pool
+fn buildtime_from_system(pool: &Pool) -> HashMap<String, time::SystemTime> {
+    pool.installed()
+        .into_iter()
+        .flat_map(|r| r.solvables())
+        .map(|solvable| {
+            (
+                solvable.nevra(),
+                time::UNIX_EPOCH + time::Duration::from_secs(solvable.buildtime()),
+            )
+        })
+        .collect()
+}
 
+fn base_manifest(
+    repos: &[ZypperRepo],
+    repo_packages: &HashMap<String, RepoPackage>,
+    installation: &Installation,
+    status_dir: &str,
+) -> Result<()> {
     let mut doc = Vec::new();
     for product in &installation.products {
         doc.push(format!("product:{}", product.full_name()));
@@ -559,11 +711,18 @@ fn base_manifest(installation: &Installation, status_dir: &str) -> GenericResult
         doc.push(format!("pattern:{}", pattern.full_name()));
     }
     for package in &installation.packages {
-        doc.push(format!(
-            "{} {}",
-            package.full_name(),
-            buildtimes.get(&package.full_name()).unwrap()
-        ));
+        let buildtime = repo_packages
+            .get(&package.full_name())
+            .map(|p| p.buildtime)
+            .ok_or_else(|| Error::MissingBuildtime(package.full_name()))?;
+        // The repository where the solver picked the package from
+        let priority = repos
+            .iter()
+            .find(|r| r.alias == package.repo)
+            .map(|r| r.priority)
+            .unwrap_or_default();
+        let provenance = format!("{}:{}", package.repo, priority);
+        doc.push(manifest_line(package, Some(buildtime), Some(&provenance)));
     }
 
     doc.sort();
@@ -573,31 +732,62 @@ fn base_manifest(installation: &Installation, status_dir: &str) -> GenericResult
     Ok(())
 }
 
-fn system_manifest(installation: &Installation, status_dir: &str) -> GenericResult<()> {
-    let buildtimes = buildtime_from_system();
+/// Package entry of a manifest: `name-evr.arch buildtime @repo
+/// vendor`.  The buildtime is `-` when it is not known, and the
+/// repository is `alias:priority`, or `local` if no repository has
+/// the package.  The vendor is the rest of the line
+fn manifest_line(
+    package: &libsolv_rs::pool::Package,
+    buildtime: Option<u64>,
+    repo: Option<&str>,
+) -> String {
+    let mut line = package.full_name();
+    if buildtime.is_none() && repo.is_none() && package.vendor.is_empty() {
+        return line;
+    }
+
+    match buildtime {
+        Some(buildtime) => line.push_str(&format!(" {}", buildtime)),
+        None => line.push_str(" -"),
+    }
+    if let Some(repo) = repo {
+        line.push_str(&format!(" @{}", repo));
+    }
+    if !package.vendor.is_empty() {
+        line.push_str(&format!(" {}", package.vendor));
+    }
+    line
+}
+
+/// The installed packages, with the installed vendor.  The repository
+/// is the one that currently has the same NEVRA, or `local` for the
+/// packages installed by hand
+fn system_manifest(
+    pool: &Pool,
+    repo_packages: &HashMap<String, RepoPackage>,
+    inst_system: &Installation,
+    status_dir: &str,
+) -> Result<()> {
+    let buildtimes = buildtime_from_system(pool);
 
     let mut doc = Vec::new();
-    for product in &installation.products {
+    for product in &inst_system.products {
         doc.push(format!("product:{}", product.full_name()));
     }
-    for pattern in &installation.patterns {
+    for pattern in &inst_system.patterns {
         doc.push(format!("pattern:{}", pattern.full_name()));
     }
-    for package in &installation.packages {
-        if buildtimes.contains_key(&package.full_name()) {
-            let buildtime = buildtimes.get(&package.full_name()).unwrap();
-
-            doc.push(format!(
-                "{} {}",
-                package.full_name(),
-                buildtime
-                    .duration_since(time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-            ));
-        } else {
-            doc.push(package.full_name());
-        }
+    for package in &inst_system.packages {
+        let buildtime = buildtimes.get(&package.full_name()).map(|b| {
+            b.duration_since(time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+        let provenance = match repo_packages.get(&package.full_name()) {
+            Some(repo_package) => format!("{}:{}", repo_package.alias, repo_package.priority),
+            None => LOCAL_REPO.to_string(),
+        };
+        doc.push(manifest_line(package, buildtime, Some(&provenance)));
     }
 
     doc.sort();
@@ -607,7 +797,70 @@ fn system_manifest(installation: &Installation, status_dir: &str) -> GenericResu
     Ok(())
 }
 
-fn gzip(file: &str) -> GenericResult<()> {
+/// Installed packages that are not in any enabled repository, like
+/// the ones installed by hand or left from a removed repository
+fn orphaned_packages(pool: &Pool, root: &path::Path) -> Result<Vec<String>> {
+    // The orphaned packages are found when the update rules are
+    // created, so the problems of the distupgrade do not matter
+    let mut solver = zypper_solver(pool, root)?;
+    solver.solve_jobs(&[Job::distupgrade().all()]);
+
+    let mut orphaned: Vec<_> = solver
+        .orphaned()
+        .iter()
+        .map(|s| s.name())
+        .filter(|n| !n.contains(':'))
+        .collect();
+    orphaned.sort();
+    orphaned.dedup();
+    Ok(orphaned)
+}
+
+/// Installed packages whose name is in some repository, but not the
+/// installed version, like the ones of a repository that does not keep
+/// the old versions
+fn orphaned_versions(
+    inst_system: &Installation,
+    repo_packages: &HashMap<String, RepoPackage>,
+    orphaned: &[String],
+) -> Vec<String> {
+    let mut versions: Vec<_> = inst_system
+        .packages
+        .iter()
+        .filter(|p| !orphaned.contains(&p.name))
+        .map(|p| p.full_name())
+        .filter(|nevra| !repo_packages.contains_key(nevra))
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// Installed packages with a vendor different from the one of the
+/// package picked for the role, also when only one of them has no
+/// vendor
+fn vendor_changes(inst_role: &Installation, inst_system: &Installation) -> Vec<String> {
+    let vendors: HashMap<_, _> = inst_role
+        .packages
+        .iter()
+        .map(|p| ((&p.name, &p.arch), &p.vendor))
+        .collect();
+
+    let mut changed: Vec<_> = inst_system
+        .packages
+        .iter()
+        .filter(|p| {
+            vendors
+                .get(&(&p.name, &p.arch))
+                .is_some_and(|v| *v != &p.vendor)
+        })
+        .map(|p| p.name.clone())
+        .collect();
+    changed.sort();
+    changed.dedup();
+    changed
+}
+
+fn gzip(file: &str) -> Result<()> {
     let file_gz = format!("{}.gz", file);
     let mut encoder =
         flate2::write::GzEncoder::new(fs::File::create(&file_gz)?, flate2::Compression::default());
@@ -631,11 +884,11 @@ fn is_prefix(element: &str, prefixes: Option<&[&str]>) -> bool {
     false
 }
 
-fn diff_and_join(
+fn diff_names(
     vec_a: &[libsolv_rs::pool::Package],
     vec_b: &[libsolv_rs::pool::Package],
     exclude: Option<&[&str]>,
-) -> String {
+) -> Vec<String> {
     let item_set: HashSet<_> = vec_b.iter().map(|item| item.name.clone()).collect();
     let mut difference: Vec<_> = vec_a
         .iter()
@@ -644,84 +897,499 @@ fn diff_and_join(
         .collect();
     difference.sort();
 
-    difference.join(" ")
+    difference
 }
 
-pub fn create_status_file(mut roles: Roles, status_dir: &str) -> GenericResult<()> {
-    if !path::Path::new(status_dir).exists() {
-        fs::create_dir(status_dir)?;
-    }
-
-    let mut status = Vec::new();
-    status.push(format!(r#"DATE="{}""#, Utc::now()));
-    status.push(format!(r#"PRODUCT="{}""#, baseproduct()?));
-
-    let release = OsRelease::new()?;
-    status.push(format!(r#"VERSION_ID="{}""#, release.version_id));
+/// Rank the roles by how close they are to the installed system
+pub fn rank_roles(roles: Roles, root: &path::Path) -> Result<RoleRanking> {
+    Ok(RoleRanking::new(&roles, &Installation::from_system(root)?))
+}
 
-    roles.apply_default();
-    let inst_system = Installation::from_system()?;
-    let role = find_closer_role(&roles, &inst_system)?.expect("Role cannot be detected");
+/// Find the role closer to the installed system, unless it is pinned,
+/// and add to it the optional packages and patterns that are
+/// installed
+fn detect_role(
+    roles: &mut Roles,
+    pool: &Pool,
+    inst_system: &Installation,
+    pinned: Option<&str>,
+) -> Result<(String, RoleRanking)> {
+    let ranking = RoleRanking::new(roles, inst_system);
+    let role = match pinned {
+        Some(role) if roles.0.contains_key(role) => role.to_string(),
+        Some(role) => return Err(Error::RoleNotFound(role.to_string())),
+        None => ranking.best().ok_or(Error::NoRoleDetected)?.role.clone(),
+    };
     // Once that we know the role, we can resolve the optional
     // packages and patterns efficiently
-    apply_optional_for_role(&role, &mut roles, &inst_system);
+    apply_optional_for_role(&role, roles, pool, inst_system)?;
+    Ok((role, ranking))
+}
+
+/// Changes needed to bring the system to the detected or pinned role.
+/// With `strict`, the exact versions of the role are installed, and
+/// the packages that are not part of the role are removed
+pub fn remediation_plan(
+    mut roles: Roles,
+    pinned: Option<&str>,
+    root: &path::Path,
+    strict: bool,
+) -> Result<Plan> {
+    let mut pool = system_pool(root)?;
+    let inst_system = Installation::from_installed(&pool, &HashSet::new());
+    let (role, _) = detect_role(&mut roles, &pool, &inst_system, pinned)?;
+    Plan::new(&mut pool, root, &role, &roles, strict)
+}
+
+pub fn create_status_file(
+    mut roles: Roles,
+    pinned: Option<&str>,
+    root: &path::Path,
+    status_dir: &str,
+) -> Result<()> {
+    if !path::Path::new(status_dir).exists() {
+        fs::create_dir(status_dir)?;
+    }
 
-    status.push(format!(r#"ROLE="{}""#, role));
+    let release = OsRelease::new(root)?;
+    let mut status = Status {
+        date: Utc::now(),
+        product: baseproduct(root)?,
+        version_id: release.version_id,
+        ..Status::default()
+    };
+
+    // One pool with the repositories and the installed system for all
+    // the reports
+    let mut pool = system_pool(root)?;
+    let repos = repo_alias(root)?;
+    let repo_packages = packages_from_repos(&pool, &repos);
+
+    let inst_system = Installation::from_installed(&pool, &HashSet::new());
+    let (role, ranking) = detect_role(&mut roles, &pool, &inst_system, pinned)?;
+
+    status.role = role.clone();
+    status.role_pinned = pinned.is_some();
+    status.role_score = ranking
+        .scores
+        .iter()
+        .find(|s| s.role == role)
+        .map(|s| s.score)
+        .unwrap_or_default();
+    status.role_confidence = ranking.confidence;
+    status.allow_vendor_change = ZypperConf::new(root)?.allow_vendor_change;
+    status.runner_up_roles = ranking
+        .runner_ups(&role, 3)
+        .iter()
+        .map(|s| format!("{}:{:.2}", s.role, s.score))
+        .collect();
 
-    // System manifest contains the list of packages expected for the
-    // role, but the buildtime from rpmdb.  Maybe should have the list
-    // of installed packages.
-    let inst_role = Installation::from_role(&role, &roles)?;
-    system_manifest(&inst_role, status_dir)?;
-    base_manifest(&inst_role, status_dir)?;
+    // The reports of the installed system do not depend on the role,
+    // so they are written also when the role cannot be resolved
+    status.outdated_packages = Outdated::from_pool(&pool).names();
+    status.orphaned_packages = orphaned_packages(&pool, root)?;
+    status.orphaned_versions =
+        orphaned_versions(&inst_system, &repo_packages, &status.orphaned_packages);
+
+    let patches = Patches::from_pool(&pool);
+    status.needed_security_patches = patches.count("security");
+    status.needed_recommended_patches = patches.count("recommended");
+    status.needed_optional_patches = patches.count("optional");
+
+    let inst_role = match Installation::from_role(&mut pool, root, &role, &roles) {
+        Ok(inst_role) => inst_role,
+        Err(Error::SolverProblems(problems)) => {
+            // Report the problems instead of failing, so the user can
+            // see why the role cannot be resolved
+            status.problems = problems.iter().map(|p| p.to_string()).collect();
+            return write_status(&status, status_dir);
+        }
+        Err(e @ Error::ProductMismatch(..)) => {
+            status.problems = vec![e.to_string()];
+            return write_status(&status, status_dir);
+        }
+        Err(e) => return Err(e),
+    };
+    system_manifest(&pool, &repo_packages, &inst_system, status_dir)?;
+    base_manifest(&repos, &repo_packages, &inst_role, status_dir)?;
 
     let mut hasher = Sha256::new();
     hasher.update(fs::read_to_string(&format!(
         "{}/base.manifest",
         status_dir
     ))?);
-    status.push(format!(
-        r#"BASE_MANIFEST_DIGEST="{:x}""#,
-        hasher.finalize_reset()
-    ));
+    status.base_manifest_digest = format!("{:x}", hasher.finalize_reset());
 
     hasher.update(fs::read_to_string(&format!(
         "{}/system.manifest",
         status_dir
     ))?);
-    status.push(format!(
-        r#"SYSTEM_MANIFEST_DIGEST="{:x}""#,
-        hasher.finalize()
-    ));
+    status.system_manifest_digest = format!("{:x}", hasher.finalize());
 
     gzip(&format!("{}/base.manifest", status_dir))?;
     gzip(&format!("{}/system.manifest", status_dir))?;
 
-    let packages_user = Installation::from_system_no_autoinstalled()?;
-    status.push(format!(
-        r#"ADDED_PATTERNS="{}""#,
-        diff_and_join(&packages_user.patterns, &inst_role.patterns, None)
-    ));
-    status.push(format!(
-        r#"REMOVED_PATTERNS="{}""#,
-        diff_and_join(&inst_role.patterns, &inst_system.patterns, None)
-    ));
-
-    status.push(format!(
-        r#"ADDED_PACKAGES="{}""#,
-        diff_and_join(
-            &packages_user.packages,
-            &inst_role.packages,
-            Some(&["patterns-"])
+    let packages_user = Installation::from_installed(&pool, &Installation::autoinstalled(root)?);
+    status.added_patterns = diff_names(&packages_user.patterns, &inst_role.patterns, None);
+    status.removed_patterns = diff_names(&inst_role.patterns, &inst_system.patterns, None);
+    status.added_packages = diff_names(
+        &packages_user.packages,
+        &inst_role.packages,
+        Some(&["patterns-"]),
+    );
+    status.removed_packages = diff_names(&inst_role.packages, &inst_system.packages, None);
+    status.vendor_changed_packages = vendor_changes(&inst_role, &inst_system);
+
+    write_status(&status, status_dir)
+}
+
+fn write_status(status: &Status, status_dir: &str) -> Result<()> {
+    let mut ostatus = fs::File::create(format!("{}/ostatus", status_dir))?;
+    ostatus.write_all(status.to_shell().as_bytes())?;
+
+    Ok(())
+}
+
+static STAGING_PREFIX: &str = ".ostatus-";
+
+/// Create an empty staging directory inside `dir`.  It is removed
+/// when dropped, unless it is moved away before.  The staging
+/// directories left by an interrupted run are removed first
+pub(crate) fn staging_dir(dir: &path::Path) -> Result<tempfile::TempDir> {
+    fs::create_dir_all(dir)?;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let leftover = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(STAGING_PREFIX);
+        if leftover && entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+
+    let staging = tempfile::Builder::new()
+        .prefix(STAGING_PREFIX)
+        .tempdir_in(dir)?;
+    fs::set_permissions(staging.path(), fs::Permissions::from_mode(0o755))?;
+    Ok(staging)
+}
+
+/// Atomically exchange two directories.  When the filesystem or the
+/// kernel cannot, they are exchanged with plain renames, and `dir_b`
+/// is missing for a moment
+fn exchange(dir_a: &path::Path, dir_b: &path::Path) -> Result<()> {
+    let dir_a_c = ffi::CString::new(dir_a.as_os_str().as_bytes())?;
+    let dir_b_c = ffi::CString::new(dir_b.as_os_str().as_bytes())?;
+    let ret = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            dir_a_c.as_ptr(),
+            libc::AT_FDCWD,
+            dir_b_c.as_ptr(),
+            libc::RENAME_EXCHANGE,
         )
-    ));
-    status.push(format!(
-        r#"REMOVED_PACKAGES="{}""#,
-        diff_and_join(&inst_role.packages, &inst_system.packages, None)
-    ));
+    };
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EINVAL) | Some(libc::ENOSYS) => exchange_by_rename(dir_a, dir_b),
+            _ => Err(err.into()),
+        };
+    }
+    Ok(())
+}
 
-    let mut ostatus = fs::File::create(&format!("{}/ostatus", status_dir))?;
-    ostatus.write_all(status.join("\n").as_bytes())?;
+/// Exchange two directories with three renames, through a sibling of
+/// `dir_a`.  If `dir_a` is a staging directory, so is the sibling, and
+/// it is removed by the next run if this one is interrupted
+fn exchange_by_rename(dir_a: &path::Path, dir_b: &path::Path) -> Result<()> {
+    let mut old = dir_a.as_os_str().to_owned();
+    old.push(".old");
+    let old = path::PathBuf::from(old);
+
+    fs::rename(dir_b, &old)?;
+    if let Err(e) = fs::rename(dir_a, dir_b) {
+        fs::rename(&old, dir_b)?;
+        return Err(e.into());
+    }
+    fs::rename(&old, dir_a)?;
+    Ok(())
+}
 
+/// Move a complete staging directory to `dir`, replacing its content
+fn replace_dir(staging: tempfile::TempDir, dir: &path::Path) -> Result<()> {
+    if dir.exists() {
+        // The old content ends in the staging directory, that is
+        // removed on drop
+        exchange(staging.path(), dir)?;
+    } else {
+        fs::rename(staging.path(), dir)?;
+    }
     Ok(())
 }
+
+/// Replace the status directory with a new status.  The status is
+/// created in a staging directory, and only when it is complete it
+/// is moved in place, so readers never see a partial status
+pub fn update_status_dir(
+    roles: Roles,
+    pinned: Option<&str>,
+    root: &path::Path,
+    status_dir: &str,
+) -> Result<()> {
+    let status_dir = path::Path::new(status_dir);
+    let parent = match status_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => path::Path::new("."),
+    };
+
+    let staging = staging_dir(parent)?;
+    create_status_file(roles, pinned, root, &staging.path().to_string_lossy())?;
+    replace_dir(staging, status_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str, &str)]) -> Config {
+        let mut cfg = Config::default();
+        for (section, key, value) in entries {
+            cfg.0.entry(section.to_string()).or_default().insert(
+                key.to_string(),
+                ConfigValue {
+                    value: value.to_string(),
+                    file: path::PathBuf::from("ostatus.cfg"),
+                },
+            );
+        }
+        cfg
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn roles_inherit_from_default() {
+        let roles = Roles::from_config(&config(&[
+            ("default", "products", "SLES"),
+            ("default", "packages", "vim"),
+            ("server", "patterns", "base"),
+            ("system", "role", "server"),
+        ]))
+        .unwrap();
+
+        assert_eq!(roles.0.len(), 1);
+        let server = &roles.0["server"];
+        assert_eq!(server.products, strings(&["SLES"]));
+        assert_eq!(server.patterns, strings(&["base"]));
+        assert_eq!(server.packages, strings(&["vim"]));
+        assert!(server.packages_opt.is_empty());
+    }
+
+    #[test]
+    fn roles_inherit_from_parents() {
+        let roles = Roles::from_config(&config(&[
+            ("default", "packages", "vim"),
+            ("base", "patterns", "base"),
+            ("base", "packages", "bash zsh"),
+            ("x11", "patterns", "x11"),
+            ("x11", "packages", "zsh xterm"),
+            ("desktop", "inherits", "base x11"),
+            ("kde", "inherits", "desktop"),
+            ("kde", "patterns+", "kde"),
+        ]))
+        .unwrap();
+
+        let desktop = &roles.0["desktop"];
+        assert_eq!(desktop.patterns, strings(&["base", "x11"]));
+        assert_eq!(desktop.packages, strings(&["bash", "zsh", "xterm"]));
+        let kde = &roles.0["kde"];
+        assert_eq!(kde.patterns, strings(&["base", "x11", "kde"]));
+        assert_eq!(kde.packages, desktop.packages);
+        assert_eq!(roles.0["base"].packages, strings(&["bash", "zsh"]));
+    }
+
+    #[test]
+    fn roles_extend_and_reduce() {
+        let roles = Roles::from_config(&config(&[
+            ("default", "packages", "vim emacs"),
+            ("server", "packages+", "nano vim"),
+            ("server", "packages-", "emacs"),
+            ("minimal", "packages", "bash"),
+            ("minimal", "packages+", "zsh"),
+            ("minimal", "packages-", "bash"),
+        ]))
+        .unwrap();
+
+        assert_eq!(roles.0["server"].packages, strings(&["vim", "nano"]));
+        assert_eq!(roles.0["minimal"].packages, strings(&["zsh"]));
+    }
+
+    #[test]
+    fn roles_with_a_cycle() {
+        let cfg = config(&[
+            ("a", "inherits", "b"),
+            ("b", "inherits", "c"),
+            ("c", "inherits", "a"),
+        ]);
+        match Roles::from_config(&cfg) {
+            Err(Error::RoleCycle(cycle)) => assert_eq!(cycle, "a -> b -> c -> a"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let cfg = config(&[("a", "inherits", "a")]);
+        assert!(matches!(Roles::from_config(&cfg), Err(Error::RoleCycle(_))));
+    }
+
+    #[test]
+    fn roles_with_a_missing_parent() {
+        let cfg = config(&[("server", "inherits", "base")]);
+        assert!(matches!(
+            Roles::from_config(&cfg),
+            Err(Error::RoleNotFound(role)) if role == "base"
+        ));
+
+        let cfg = config(&[
+            ("system", "role", "server"),
+            ("server", "inherits", "system"),
+        ]);
+        assert!(matches!(
+            Roles::from_config(&cfg),
+            Err(Error::RoleNotFound(role)) if role == "system"
+        ));
+    }
+
+    #[test]
+    fn role_for_other_products() {
+        let roles = Roles::from_config(&config(&[
+            ("server", "products", "SLES SLES_SAP"),
+            ("desktop", "products", "SLED"),
+            ("minimal", "patterns", "base"),
+        ]))
+        .unwrap();
+        let mut pool = Pool::new();
+        assert!(role_jobs(&mut pool, "SLES", "server", &roles).is_ok());
+        assert!(role_jobs(&mut pool, "SLES", "minimal", &roles).is_ok());
+        assert!(matches!(
+            role_jobs(&mut pool, "SLES", "desktop", &roles),
+            Err(Error::ProductMismatch(role, product)) if role == "desktop" && product == "SLES"
+        ));
+    }
+
+    #[test]
+    fn system_config() {
+        let cfg = config(&[("system", "role", "server")]);
+        assert_eq!(
+            SystemConfig::from_config(&cfg).role.as_deref(),
+            Some("server")
+        );
+        let cfg = config(&[("system", "role", "")]);
+        assert!(SystemConfig::from_config(&cfg).role.is_none());
+        assert!(SystemConfig::from_config(&Config::default()).role.is_none());
+    }
+
+    #[test]
+    fn vendor_changes_with_an_empty_vendor() {
+        let installation = |vendors: &[(&str, &str)]| Installation {
+            products: Vec::new(),
+            patterns: Vec::new(),
+            packages: vendors
+                .iter()
+                .map(|(name, vendor)| libsolv_rs::pool::Package {
+                    name: name.to_string(),
+                    version: "1.0-1".to_string(),
+                    arch: "x86_64".to_string(),
+                    vendor: vendor.to_string(),
+                    repo: "repo".to_string(),
+                })
+                .collect(),
+        };
+        let inst_role = installation(&[("a", "SUSE"), ("b", "SUSE"), ("c", ""), ("d", "SUSE")]);
+        let inst_system = installation(&[("a", "SUSE"), ("b", ""), ("c", "SUSE"), ("d", "Other")]);
+        assert_eq!(
+            vendor_changes(&inst_role, &inst_system),
+            strings(&["b", "c", "d"])
+        );
+    }
+
+    fn staged(parent: &path::Path, content: &str) -> tempfile::TempDir {
+        let staging = staging_dir(parent).unwrap();
+        fs::write(staging.path().join("ostatus"), content).unwrap();
+        staging
+    }
+
+    fn leftovers(dir: &path::Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(STAGING_PREFIX))
+            .collect()
+    }
+
+    #[test]
+    fn replace_an_existing_status_dir() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("status");
+        replace_dir(staged(parent.path(), "first"), &dir).unwrap();
+        fs::write(dir.join("stale"), "first").unwrap();
+
+        replace_dir(staged(parent.path(), "second"), &dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("ostatus")).unwrap(), "second");
+        assert!(!dir.join("stale").exists());
+        assert!(leftovers(parent.path()).is_empty());
+
+        // Without RENAME_EXCHANGE
+        fs::write(dir.join("stale"), "second").unwrap();
+        let staging = staged(parent.path(), "third");
+        exchange_by_rename(staging.path(), &dir).unwrap();
+        drop(staging);
+        assert_eq!(fs::read_to_string(dir.join("ostatus")).unwrap(), "third");
+        assert!(!dir.join("stale").exists());
+        assert!(leftovers(parent.path()).is_empty());
+    }
+
+    #[test]
+    fn remove_leftover_staging_dirs() {
+        let parent = tempfile::tempdir().unwrap();
+        let leftover = parent.path().join(format!("{}crashed", STAGING_PREFIX));
+        fs::create_dir(&leftover).unwrap();
+        fs::write(leftover.join("ostatus"), "partial").unwrap();
+
+        let staging = staging_dir(parent.path()).unwrap();
+        assert!(!leftover.exists());
+        assert_eq!(leftovers(parent.path()).len(), 1);
+        drop(staging);
+        assert!(leftovers(parent.path()).is_empty());
+    }
+
+    #[test]
+    fn names_of_an_installation() {
+        let package = |name: &str, arch: &str| libsolv_rs::pool::Package {
+            name: name.to_string(),
+            version: "1.0-1".to_string(),
+            arch: arch.to_string(),
+            vendor: String::new(),
+            repo: "repo".to_string(),
+        };
+        let installation = Installation {
+            products: vec![package("SLES", "x86_64")],
+            patterns: vec![package("base", "noarch")],
+            packages: vec![package("vim", "x86_64")],
+        };
+        assert_eq!(
+            installation.canonical_names(),
+            strings(&[
+                "product:SLES-1.0-1.x86_64",
+                "pattern:base-1.0-1.noarch",
+                "vim-1.0-1.x86_64"
+            ])
+        );
+        assert_eq!(
+            installation.name_archs(),
+            strings(&["product:SLES.x86_64", "pattern:base.noarch", "vim.x86_64"])
+        );
+    }
+}
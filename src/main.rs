@@ -1,7 +1,68 @@
-use std::fs;
 use std::path;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Text,
+    Shell,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validate the configuration files against the repositories of
+    /// the system
+    CheckConfig,
+
+    /// List the configuration files, from the lowest to the highest
+    /// precedence
+    Config {
+        /// Show the merged configuration, and the file of each value
+        #[clap(long, value_parser)]
+        dump: bool,
+    },
+
+    /// Compare two status directories or two manifests
+    Diff {
+        /// Old status directory or manifest
+        old: path::PathBuf,
+
+        /// New status directory or manifest
+        new: path::PathBuf,
+    },
+
+    /// List the snapshots stored in the history
+    History,
+
+    /// List the installed packages with a newer version in the
+    /// repositories
+    Outdated,
+
+    /// List the patches of the repositories that are not applied
+    Patches,
+
+    /// Show the changes needed to bring the system to the detected
+    /// role
+    Plan {
+        /// Install the exact versions of the role, and remove the
+        /// installed packages that are not part of it
+        #[clap(long, value_parser)]
+        strict: bool,
+    },
+
+    /// Rank the roles by how close they are to the installed system
+    Rank,
+
+    /// Print the commands that bring the system back to its detected
+    /// role, undoing the added and removed packages and patterns
+    Remediate {
+        /// Use transactional-update instead of zypper.  By default it
+        /// is used on MicroOS
+        #[clap(long, value_parser)]
+        transactional: bool,
+    },
+}
 
 /// Track and report of the OS status
 #[derive(Parser, Debug)]
@@ -11,38 +72,311 @@ struct Args {
     #[clap(short, long, value_parser)]
     update: bool,
 
-    /// Config file name
+    /// Config file name.  It takes precedence over the files in
+    /// /usr/etc/ostatus and /etc/ostatus
     #[clap(short, long, value_parser)]
     config: Option<path::PathBuf>,
 
     /// Directory where to store the information
     #[clap(short, long, value_parser)]
     directory: Option<String>,
+
+    /// Keep the previous status in a history of snapshots.  The
+    /// status of a directory without history becomes the first
+    /// snapshot.  Once there is a history, it is always used
+    #[clap(long, value_parser)]
+    history: bool,
+
+    /// Maximum number of snapshots kept in the history
+    #[clap(long, value_parser)]
+    history_max_count: Option<usize>,
+
+    /// Maximum age, in days, of the snapshots kept in the history
+    #[clap(long, value_parser)]
+    history_max_age: Option<i64>,
+
+    /// Role of the system, instead of the detected one.  It can also
+    /// be set with `role` in the `[system]` section of the config
+    #[clap(long, value_parser)]
+    role: Option<String>,
+
+    /// Root directory of the system to inspect
+    #[clap(short, long, value_parser, default_value = "/")]
+    root: path::PathBuf,
+
+    /// Output format of the report
+    #[clap(short, long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+fn show_status(status_dir: &str, format: Format) -> ostatus::GenericResult<()> {
+    let current_dir = ostatus::History::new(status_dir).current_dir();
+    if !current_dir.join("ostatus").exists() {
+        return Err(format!("No status found in {}, run with --update", status_dir).into());
+    }
+
+    let status = ostatus::Status::from_file(&current_dir.to_string_lossy())?;
+    match format {
+        Format::Text => print!("{}", status.to_text()?),
+        Format::Shell => println!("{}", status.to_shell()),
+        Format::Json => println!("{}", status.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_diff(old: &path::Path, new: &path::Path, format: Format) -> ostatus::GenericResult<()> {
+    let diff = ostatus::ManifestDiff::new(old, new)?;
+    match format {
+        Format::Text => print!("{}", diff.to_text()?),
+        Format::Shell => println!("{}", diff.to_shell()),
+        Format::Json => println!("{}", diff.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_history(status_dir: &str, format: Format) -> ostatus::GenericResult<()> {
+    let snapshots = ostatus::History::new(status_dir).snapshots()?;
+    match format {
+        Format::Text => {
+            for snapshot in snapshots {
+                println!("{}", snapshot.to_text()?);
+            }
+        }
+        Format::Shell => {
+            for snapshot in snapshots {
+                println!("{}", snapshot.to_shell());
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&snapshots)?),
+    }
+
+    Ok(())
+}
+
+fn show_remediation(
+    status_dir: &str,
+    root: &path::Path,
+    transactional: bool,
+    format: Format,
+) -> ostatus::GenericResult<()> {
+    let current_dir = ostatus::History::new(status_dir).current_dir();
+    if !current_dir.join("ostatus").exists() {
+        return Err(format!("No status found in {}, run with --update", status_dir).into());
+    }
+
+    let status = ostatus::Status::from_file(&current_dir.to_string_lossy())?;
+    let transactional = transactional || ostatus::is_transactional(root)?;
+    let remediation = ostatus::Remediation::new(&status, transactional);
+    match format {
+        Format::Text | Format::Shell => print!("{}", remediation.to_text()?),
+        Format::Json => println!("{}", remediation.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn check_config(
+    cfgs: &[path::PathBuf],
+    root: &path::Path,
+    format: Format,
+) -> ostatus::GenericResult<()> {
+    let check = ostatus::ConfigCheck::new(cfgs, root)?;
+    match format {
+        Format::Text => print!("{}", check.to_text()?),
+        Format::Shell => println!("{}", check.to_shell()),
+        Format::Json => println!("{}", check.to_json()?),
+    }
+
+    match check.errors() {
+        0 => Ok(()),
+        errors => Err(format!("{} errors found in the configuration", errors).into()),
+    }
+}
+
+fn show_config(cfgs: &[path::PathBuf], dump: bool, format: Format) -> ostatus::GenericResult<()> {
+    if !dump {
+        for cfg in cfgs {
+            println!("{}", cfg.display());
+        }
+        return Ok(());
+    }
+
+    let config = ostatus::Config::load(cfgs)?;
+    match format {
+        Format::Text => print!("{}", config.to_text()?),
+        Format::Shell => println!("{}", config.to_shell()),
+        Format::Json => println!("{}", config.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_outdated(root: &path::Path, format: Format) -> ostatus::GenericResult<()> {
+    let outdated = ostatus::Outdated::new(root)?;
+    match format {
+        Format::Text => print!("{}", outdated.to_text()?),
+        Format::Shell => println!("{}", outdated.to_shell()),
+        Format::Json => println!("{}", outdated.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_patches(root: &path::Path, format: Format) -> ostatus::GenericResult<()> {
+    let patches = ostatus::Patches::new(root)?;
+    match format {
+        Format::Text => print!("{}", patches.to_text()?),
+        Format::Shell => println!("{}", patches.to_shell()),
+        Format::Json => println!("{}", patches.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_ranking(
+    roles: ostatus::Roles,
+    root: &path::Path,
+    format: Format,
+) -> ostatus::GenericResult<()> {
+    let ranking = ostatus::rank_roles(roles, root)?;
+    match format {
+        Format::Text => print!("{}", ranking.to_text()?),
+        Format::Shell => println!("{}", ranking.to_shell()),
+        Format::Json => println!("{}", ranking.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn show_plan(
+    roles: ostatus::Roles,
+    pinned: Option<&str>,
+    root: &path::Path,
+    strict: bool,
+    format: Format,
+) -> ostatus::GenericResult<()> {
+    let plan = ostatus::remediation_plan(roles, pinned, root, strict)?;
+    match format {
+        Format::Text => print!("{}", plan.to_text()?),
+        Format::Shell => println!("{}", plan.to_shell()),
+        Format::Json => println!("{}", plan.to_json()?),
+    }
+
+    Ok(())
+}
+
+fn configs(args: &Args) -> ostatus::GenericResult<Vec<path::PathBuf>> {
+    let mut cfgs = ostatus::find_configs(&args.root)?;
+    if let Some(config) = &args.config {
+        cfgs.push(config.clone());
+    }
+
+    Ok(cfgs)
+}
+
+fn config(args: &Args) -> ostatus::GenericResult<ostatus::Config> {
+    Ok(ostatus::Config::load(&configs(args)?)?)
+}
+
+/// Role set in the command line or in the config, if any
+fn pinned_role(args: &Args, cfg: &ostatus::Config) -> Option<String> {
+    if args.role.is_some() {
+        return args.role.clone();
+    }
+
+    ostatus::SystemConfig::from_config(cfg).role
 }
 
 fn run() -> ostatus::GenericResult<()> {
     let args = Args::parse();
 
-    let mut cfgs = ostatus::find_configs()?;
-    if let Some(config) = args.config {
-        cfgs.push(config);
+    if let Some(Command::Diff { old, new }) = &args.command {
+        return show_diff(old, new, args.format);
+    }
+
+    if let Some(Command::Config { dump }) = &args.command {
+        return show_config(&configs(&args)?, *dump, args.format);
     }
 
-    let roles = ostatus::Roles::from_config(&cfgs)?;
+    if let Some(Command::CheckConfig) = &args.command {
+        return check_config(&configs(&args)?, &args.root, args.format);
+    }
+
+    if let Some(Command::Outdated) = &args.command {
+        return show_outdated(&args.root, args.format);
+    }
+
+    if let Some(Command::Patches) = &args.command {
+        return show_patches(&args.root, args.format);
+    }
+
+    if let Some(Command::Plan { strict }) = &args.command {
+        let cfg = config(&args)?;
+        let pinned = pinned_role(&args, &cfg);
+        return show_plan(
+            ostatus::Roles::from_config(&cfg)?,
+            pinned.as_deref(),
+            &args.root,
+            *strict,
+            args.format,
+        );
+    }
+
+    if let Some(Command::Rank) = &args.command {
+        let cfg = config(&args)?;
+        return show_ranking(ostatus::Roles::from_config(&cfg)?, &args.root, args.format);
+    }
+
+    let status_dir = args.directory.clone().unwrap_or_else(|| {
+        args.root
+            .join(ostatus::STATUS_DIR.trim_start_matches('/'))
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    if let Some(Command::History) = &args.command {
+        return show_history(&status_dir, args.format);
+    }
+
+    if let Some(Command::Remediate { transactional }) = &args.command {
+        return show_remediation(&status_dir, &args.root, *transactional, args.format);
+    }
 
-    let status_dir = args
-        .directory
-        .unwrap_or_else(|| ostatus::STATUS_DIR.to_string());
     if args.update {
-        if path::Path::new(&status_dir).exists() {
-            fs::remove_dir_all(&status_dir)?;
+        let cfg = config(&args)?;
+        let roles = ostatus::Roles::from_config(&cfg)?;
+        let pinned = pinned_role(&args, &cfg);
+
+        let history = ostatus::History::new(&status_dir);
+        if args.history {
+            history.migrate()?;
         }
-        ostatus::create_status_file(roles, &status_dir)?;
-    }
+        if args.history || history.exists() {
+            let staging = history.staging()?;
+            ostatus::create_status_file(
+                roles,
+                pinned.as_deref(),
+                &args.root,
+                &staging.path().to_string_lossy(),
+            )?;
+            history.commit(staging)?;
 
-    // TODO Show the status file.
+            let retention = ostatus::Retention {
+                max_count: args.history_max_count,
+                max_age: args.history_max_age.map(chrono::Duration::days),
+            };
+            history.prune(&retention)?;
+        } else {
+            ostatus::update_status_dir(roles, pinned.as_deref(), &args.root, &status_dir)?;
+        }
+    }
 
-    Ok(())
+    show_status(&status_dir, args.format)
 }
 
 fn main() {
@@ -0,0 +1,151 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path;
+
+use libsolv_rs::pool::{EvrCmpMode, Pool};
+use libsolv_rs::solvable::Solvable;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{system_pool, Result};
+
+/// Installed package with a newer version in the repositories
+#[derive(Debug, Serialize)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub arch: String,
+    pub installed_evr: String,
+    pub available_evr: String,
+    /// Alias of the repository with the newer version
+    pub repo: String,
+}
+
+/// Installed packages that can be updated from the enabled
+/// repositories
+#[derive(Debug, Default, Serialize)]
+pub struct Outdated {
+    pub packages: Vec<OutdatedPackage>,
+}
+
+impl Outdated {
+    /// Compare each installed package with the best candidate of the
+    /// same name and arch.  Like zypper, the candidate comes from the
+    /// repository with the best priority (the higher value in libsolv),
+    /// and then it is the one with the higher version
+    pub fn new(root: &path::Path) -> Result<Outdated> {
+        Ok(Outdated::from_pool(&system_pool(root)?))
+    }
+
+    /// Like `new`, with a pool that has the repositories and the
+    /// installed system loaded
+    pub(crate) fn from_pool(pool: &Pool) -> Outdated {
+        let Some(installed) = pool.installed().map(|r| r.id()) else {
+            return Outdated::default();
+        };
+
+        let better = |a: &Solvable, b: &Solvable| {
+            a.repo()
+                .priority()
+                .cmp(&b.repo().priority())
+                .then_with(|| pool.evrcmp_str(&a.evr(), &b.evr(), EvrCmpMode::Compare))
+                == cmp::Ordering::Greater
+        };
+
+        let mut candidates: HashMap<(String, String), Solvable> = HashMap::new();
+        for solvable in pool.solvables() {
+            if solvable.repo().id() == installed {
+                continue;
+            }
+            let key = (solvable.name(), solvable.arch());
+            match candidates.get(&key) {
+                Some(candidate) if !better(&solvable, candidate) => (),
+                _ => {
+                    candidates.insert(key, solvable);
+                }
+            }
+        }
+
+        let mut outdated = Outdated::default();
+        for solvable in pool.solvables().filter(|s| s.repo().id() == installed) {
+            let name = solvable.name();
+            // Patterns and products follow the packages that provide
+            // them
+            if name.contains(':') {
+                continue;
+            }
+            let Some(candidate) = candidates.get(&(name.clone(), solvable.arch())) else {
+                continue;
+            };
+            if pool.evrcmp_str(&solvable.evr(), &candidate.evr(), EvrCmpMode::Compare)
+                == cmp::Ordering::Less
+            {
+                outdated.packages.push(OutdatedPackage {
+                    name,
+                    arch: solvable.arch(),
+                    installed_evr: solvable.evr(),
+                    available_evr: candidate.evr(),
+                    repo: candidate.repo().name(),
+                });
+            }
+        }
+        outdated
+            .packages
+            .sort_by(|a, b| (&a.name, &a.arch).cmp(&(&b.name, &b.arch)));
+
+        outdated
+    }
+
+    /// Names of the outdated packages
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.packages.iter().map(|p| p.name.clone()).collect();
+        names.dedup();
+        names
+    }
+
+    pub fn to_shell(&self) -> String {
+        let mut lines = Vec::new();
+        for package in &self.packages {
+            lines.push(
+                [
+                    var("NAME", &package.name),
+                    var("ARCH", &package.arch),
+                    var("INSTALLED", &package.installed_evr),
+                    var("AVAILABLE", &package.available_evr),
+                    var("REPO", &package.repo),
+                ]
+                .join(" "),
+            );
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        if self.packages.is_empty() {
+            writeln!(text, "All the packages are up to date")?;
+            return Ok(text);
+        }
+
+        writeln!(text, "Outdated packages ({}):", self.packages.len())?;
+        for package in &self.packages {
+            writeln!(
+                text,
+                "  {}.{}: {} -> {} ({})",
+                package.name,
+                package.arch,
+                package.installed_evr,
+                package.available_evr,
+                package.repo
+            )?;
+        }
+
+        Ok(text)
+    }
+}
@@ -0,0 +1,164 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path;
+
+use libsolv_rs::pool::{EvrCmpMode, Pool};
+use libsolv_rs::selection::Selection;
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{system_pool, Result};
+
+/// Patch that fixes packages installed in the system
+#[derive(Debug, Serialize)]
+pub struct NeededPatch {
+    pub name: String,
+    pub evr: String,
+    /// `security`, `recommended`, `optional`, ...
+    pub category: String,
+    /// `critical`, `important`, `moderate`, `low`, ...
+    pub severity: String,
+    /// CVEs fixed by the patch
+    pub cves: Vec<String>,
+}
+
+/// Patches from the updateinfo of the repositories that are not
+/// applied
+#[derive(Debug, Default, Serialize)]
+pub struct Patches {
+    pub needed: Vec<NeededPatch>,
+}
+
+impl Patches {
+    /// A patch is needed when it conflicts with an installed package.
+    /// The patches conflict with the versions older than the ones that
+    /// they fix
+    pub fn new(root: &path::Path) -> Result<Patches> {
+        Ok(Patches::from_pool(&system_pool(root)?))
+    }
+
+    /// Like `new`, with a pool that has the repositories and the
+    /// installed system loaded, and the whatprovides index created
+    pub(crate) fn from_pool(pool: &Pool) -> Patches {
+        let Some(installed) = pool.installed().map(|r| r.id()) else {
+            return Patches::default();
+        };
+
+        // A patch can be in several repositories, or with several
+        // versions, and it is reported once, with the higher version
+        let mut needed: HashMap<String, NeededPatch> = HashMap::new();
+        for solvable in pool.solvables() {
+            let Some(name) = solvable
+                .name()
+                .strip_prefix("patch:")
+                .map(|n| n.to_string())
+            else {
+                continue;
+            };
+            if solvable.repo().id() == installed {
+                continue;
+            }
+
+            let is_needed = solvable.conflicts().into_iter().any(|conflict| {
+                Selection::whatprovides(pool, conflict).is_some_and(|providers| {
+                    providers
+                        .solvables(pool)
+                        .iter()
+                        .any(|s| s.repo().id() == installed)
+                })
+            });
+            if !is_needed {
+                continue;
+            }
+            if let Some(patch) = needed.get(&name) {
+                if pool.evrcmp_str(&patch.evr, &solvable.evr(), EvrCmpMode::Compare)
+                    != cmp::Ordering::Less
+                {
+                    continue;
+                }
+            }
+            needed.insert(
+                name.clone(),
+                NeededPatch {
+                    name,
+                    evr: solvable.evr(),
+                    category: solvable.patch_category(),
+                    severity: solvable.update_severity(),
+                    cves: solvable
+                        .update_references()
+                        .into_iter()
+                        .filter(|r| r.kind == "cve")
+                        .map(|r| r.id)
+                        .collect(),
+                },
+            );
+        }
+
+        let mut patches = Patches {
+            needed: needed.into_values().collect(),
+        };
+        patches
+            .needed
+            .sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+
+        patches
+    }
+
+    /// Number of needed patches of the category
+    pub fn count(&self, category: &str) -> usize {
+        self.needed
+            .iter()
+            .filter(|p| p.category == category)
+            .count()
+    }
+
+    pub fn to_shell(&self) -> String {
+        let mut lines = vec![
+            var("NEEDED_SECURITY_PATCHES", self.count("security")),
+            var("NEEDED_RECOMMENDED_PATCHES", self.count("recommended")),
+            var("NEEDED_OPTIONAL_PATCHES", self.count("optional")),
+        ];
+        for patch in &self.needed {
+            lines.push(
+                [
+                    var("PATCH", &patch.name),
+                    var("CATEGORY", &patch.category),
+                    var("SEVERITY", &patch.severity),
+                    var("CVES", patch.cves.join(" ")),
+                ]
+                .join(" "),
+            );
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        if self.needed.is_empty() {
+            writeln!(text, "No patches needed")?;
+            return Ok(text);
+        }
+
+        writeln!(text, "Needed patches ({}):", self.needed.len())?;
+        for patch in &self.needed {
+            write!(text, "  {} [{}", patch.name, patch.category)?;
+            if !patch.severity.is_empty() {
+                write!(text, ", {}", patch.severity)?;
+            }
+            write!(text, "]")?;
+            if !patch.cves.is_empty() {
+                write!(text, " {}", patch.cves.join(" "))?;
+            }
+            writeln!(text)?;
+        }
+
+        Ok(text)
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path;
+
+use libsolv_rs::job::Job;
+use libsolv_rs::pool::Pool;
+use libsolv_rs::selection::{Selection, SelectionFlags};
+use libsolv_rs::solvable::Solvable;
+use libsolv_rs::transaction::{TransactionMode, TransactionType};
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{zypper_solver, Error, Installation, Result, Roles};
+
+/// Installed package replaced by a different version
+#[derive(Debug, Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub arch: String,
+    pub old_evr: String,
+    pub new_evr: String,
+}
+
+impl PackageChange {
+    fn new(old: &Solvable, new: Option<Solvable>) -> PackageChange {
+        PackageChange {
+            name: old.name(),
+            arch: old.arch(),
+            old_evr: old.evr(),
+            new_evr: new.map(|n| n.evr()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Installed package replaced by one from a different vendor
+#[derive(Debug, Serialize)]
+pub struct VendorChange {
+    pub name: String,
+    /// Empty when the package has no vendor
+    pub old_vendor: String,
+    pub new_vendor: String,
+}
+
+/// Installed package replaced by one with a different arch
+#[derive(Debug, Serialize)]
+pub struct ArchChange {
+    pub name: String,
+    pub old_arch: String,
+    pub new_arch: String,
+}
+
+/// What zypper would do to bring the installed system to a role
+#[derive(Default, Debug, Serialize)]
+pub struct Plan {
+    pub role: String,
+    pub installs: Vec<String>,
+    pub erases: Vec<String>,
+    pub upgrades: Vec<PackageChange>,
+    pub downgrades: Vec<PackageChange>,
+    /// Installed packages replaced by a package with the same version
+    /// but different content
+    pub changes: Vec<PackageChange>,
+    pub reinstalls: Vec<String>,
+    pub vendor_changes: Vec<VendorChange>,
+    pub arch_changes: Vec<ArchChange>,
+    /// Steps of any other kind of the transaction
+    pub others: Vec<String>,
+}
+
+impl Plan {
+    /// Solve the role against the installed system.  The reference
+    /// installation of the role is installed, and the solver decides
+    /// what else changes.  With `strict`, its exact versions are
+    /// installed, and the installed packages that are not part of it
+    /// are removed.
+    /// The pool has the repositories and the installed system
+    pub fn new(
+        pool: &mut Pool,
+        root: &path::Path,
+        role: &str,
+        roles: &Roles,
+        strict: bool,
+    ) -> Result<Plan> {
+        let reference = Installation::from_role(pool, root, role, roles)?;
+        let pool = &*pool;
+
+        // Without `strict` the elements are pinned by name and arch, so
+        // the installed versions are kept instead of synced to the
+        // ones of the reference installation
+        let (names, flags) = if strict {
+            (reference.canonical_names(), SelectionFlags::CANON)
+        } else {
+            (
+                reference.name_archs(),
+                SelectionFlags::NAME | SelectionFlags::DOTARCH,
+            )
+        };
+        let mut jobs = Vec::new();
+        for name in names {
+            match Selection::make(pool, &name, flags) {
+                Some(selection) if !selection.is_empty() => {
+                    jobs.extend(Job::install().selection(&selection))
+                }
+                _ => return Err(Error::PackageNotFound(name)),
+            }
+        }
+        if strict {
+            let installed = pool.installed().map(|r| r.id());
+            let reference_packages: HashSet<_> =
+                reference.packages.iter().map(|p| &p.name).collect();
+            for solvable in pool
+                .solvables()
+                .filter(|s| Some(s.repo().id()) == installed)
+            {
+                let name = solvable.name();
+                // Patterns and products are pseudo packages, they go
+                // away with the packages that provide them
+                if !name.contains(':') && !reference_packages.contains(&name) {
+                    jobs.push(Job::erase().solvable(&solvable));
+                }
+            }
+        }
+
+        let mut solver = zypper_solver(pool, root)?;
+        if solver.solve_jobs(&jobs) > 0 {
+            return Err(Error::SolverProblems(solver.problems()));
+        }
+
+        let mut plan = Plan {
+            role: role.to_string(),
+            ..Plan::default()
+        };
+        // The id of the vendor is 0 when the package has none
+        let vendor = |id| {
+            if id == 0 {
+                String::new()
+            } else {
+                pool.id2str(id)
+            }
+        };
+        let mut transaction = solver.transaction();
+        let mode = TransactionMode::SHOW_OBSOLETES | TransactionMode::OBSOLETE_IS_UPGRADE;
+        for class in transaction.classes(mode) {
+            let solvables = transaction.class_solvables(mode, &class);
+            match class.kind {
+                TransactionType::Install | TransactionType::MultiInstall => {
+                    plan.installs.extend(solvables.iter().map(|s| s.nevra()))
+                }
+                TransactionType::Erase => plan.erases.extend(solvables.iter().map(|s| s.nevra())),
+                TransactionType::Upgraded => {
+                    for s in solvables {
+                        let new = transaction.replacement(&s);
+                        plan.upgrades.push(PackageChange::new(&s, new));
+                    }
+                }
+                TransactionType::Downgraded => {
+                    for s in solvables {
+                        let new = transaction.replacement(&s);
+                        plan.downgrades.push(PackageChange::new(&s, new));
+                    }
+                }
+                TransactionType::Changed => {
+                    for s in solvables {
+                        let new = transaction.replacement(&s);
+                        plan.changes.push(PackageChange::new(&s, new));
+                    }
+                }
+                TransactionType::Reinstalled | TransactionType::MultiReinstall => {
+                    plan.reinstalls.extend(solvables.iter().map(|s| s.nevra()))
+                }
+                TransactionType::VendorChange => {
+                    for s in solvables {
+                        plan.vendor_changes.push(VendorChange {
+                            name: s.name(),
+                            old_vendor: vendor(class.from),
+                            new_vendor: vendor(class.to),
+                        });
+                    }
+                }
+                TransactionType::ArchChange => {
+                    for s in solvables {
+                        plan.arch_changes.push(ArchChange {
+                            name: s.name(),
+                            old_arch: pool.id2str(class.from),
+                            new_arch: pool.id2str(class.to),
+                        });
+                    }
+                }
+                _ => plan.others.extend(solvables.iter().map(|s| s.nevra())),
+            }
+        }
+        // An upgrade or downgrade can also change the vendor or the
+        // arch, and it is only listed once
+        let replaced: HashSet<_> = plan
+            .upgrades
+            .iter()
+            .chain(&plan.downgrades)
+            .map(|c| c.name.clone())
+            .collect();
+        plan.vendor_changes.retain(|c| !replaced.contains(&c.name));
+        plan.arch_changes.retain(|c| !replaced.contains(&c.name));
+
+        plan.installs.sort();
+        plan.erases.sort();
+        plan.reinstalls.sort();
+        plan.others.sort();
+
+        Ok(plan)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.installs.is_empty()
+            && self.erases.is_empty()
+            && self.upgrades.is_empty()
+            && self.downgrades.is_empty()
+            && self.changes.is_empty()
+            && self.reinstalls.is_empty()
+            && self.vendor_changes.is_empty()
+            && self.arch_changes.is_empty()
+            && self.others.is_empty()
+    }
+
+    pub fn to_shell(&self) -> String {
+        let changes = |changes: &[PackageChange]| {
+            changes
+                .iter()
+                .map(|c| format!("{}-{}.{}", c.name, c.new_evr, c.arch))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let vendor_changes = self
+            .vendor_changes
+            .iter()
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let arch_changes = self
+            .arch_changes
+            .iter()
+            .map(|c| format!("{}.{}", c.name, c.new_arch))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        [
+            var("ROLE", &self.role),
+            var("INSTALLS", self.installs.join(" ")),
+            var("ERASES", self.erases.join(" ")),
+            var("UPGRADES", changes(&self.upgrades)),
+            var("DOWNGRADES", changes(&self.downgrades)),
+            var("CHANGES", changes(&self.changes)),
+            var("REINSTALLS", self.reinstalls.join(" ")),
+            var("VENDOR_CHANGES", vendor_changes),
+            var("ARCH_CHANGES", arch_changes),
+            var("OTHERS", self.others.join(" ")),
+        ]
+        .join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        if self.is_empty() {
+            writeln!(text, "The system matches the role {}", self.role)?;
+            return Ok(text);
+        }
+
+        writeln!(text, "To bring the system to the role {}:", self.role)?;
+        for (title, names) in [
+            ("Install", &self.installs),
+            ("Erase", &self.erases),
+            ("Reinstall", &self.reinstalls),
+        ] {
+            writeln!(text, "{} ({}):", title, names.len())?;
+            for name in names {
+                writeln!(text, "  {}", name)?;
+            }
+        }
+
+        for (title, changes) in [
+            ("Upgrade", &self.upgrades),
+            ("Downgrade", &self.downgrades),
+            ("Change", &self.changes),
+        ] {
+            writeln!(text, "{} ({}):", title, changes.len())?;
+            for change in changes {
+                writeln!(
+                    text,
+                    "  {}.{}: {} -> {}",
+                    change.name, change.arch, change.old_evr, change.new_evr
+                )?;
+            }
+        }
+
+        writeln!(text, "Change vendor ({}):", self.vendor_changes.len())?;
+        let vendor = |vendor: &str| {
+            if vendor.is_empty() {
+                "(none)".to_string()
+            } else {
+                vendor.to_string()
+            }
+        };
+        for change in &self.vendor_changes {
+            writeln!(
+                text,
+                "  {}: {} -> {}",
+                change.name,
+                vendor(&change.old_vendor),
+                vendor(&change.new_vendor)
+            )?;
+        }
+
+        writeln!(text, "Change arch ({}):", self.arch_changes.len())?;
+        for change in &self.arch_changes {
+            writeln!(
+                text,
+                "  {}: {} -> {}",
+                change.name, change.old_arch, change.new_arch
+            )?;
+        }
+
+        if !self.others.is_empty() {
+            writeln!(text, "Other ({}):", self.others.len())?;
+            for name in &self.others {
+                writeln!(text, "  {}", name)?;
+            }
+        }
+
+        Ok(text)
+    }
+}
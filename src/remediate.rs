@@ -0,0 +1,136 @@
+use std::fmt::Write as _;
+use std::path;
+
+use serde::Serialize;
+
+use crate::shell::quote;
+use crate::{OsRelease, Result, Status};
+
+/// Commands that bring the system back to its detected role, undoing
+/// the drift recorded in the status
+#[derive(Debug, Serialize)]
+pub struct Remediation {
+    pub role: String,
+    pub transactional: bool,
+    pub commands: Vec<String>,
+    /// Why the role cannot be resolved, if it cannot
+    pub problems: Vec<String>,
+}
+
+/// MicroOS and SLE Micro have a read-only root, and the packages are
+/// changed with `transactional-update`
+pub fn is_transactional(root: &path::Path) -> Result<bool> {
+    Ok(OsRelease::new(root)?.id.contains("micro"))
+}
+
+impl Remediation {
+    /// With `transactional`, every command after the first one
+    /// continues its snapshot, so all the changes are applied together
+    /// after the reboot.  The commands never prompt, as the script
+    /// stops at the first failure
+    pub fn new(status: &Status, transactional: bool) -> Remediation {
+        let command = |first: bool, action: &str, kind: Option<&str>, names: &[String]| {
+            let tool = match (transactional, first) {
+                (true, true) => "transactional-update -n pkg",
+                (true, false) => "transactional-update -n --continue pkg",
+                (false, _) => "zypper --non-interactive",
+            };
+            let names = names.iter().map(quote).collect::<Vec<_>>().join(" ");
+            match kind {
+                Some(kind) => format!("{} {} --type {} {}", tool, action, kind, names),
+                None => format!("{} {} {}", tool, action, names),
+            }
+        };
+
+        let mut commands = Vec::new();
+        for (action, kind, names) in [
+            ("install", Some("pattern"), &status.removed_patterns),
+            ("install", None, &status.removed_packages),
+            ("remove", Some("pattern"), &status.added_patterns),
+            ("remove", None, &status.added_packages),
+        ] {
+            if !names.is_empty() {
+                commands.push(command(commands.is_empty(), action, kind, names));
+            }
+        }
+
+        Remediation {
+            role: status.role.clone(),
+            transactional,
+            commands,
+            problems: status.problems.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Shell script with the commands, to be reviewed before running
+    /// it
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        writeln!(text, "#!/bin/sh")?;
+        writeln!(text, "# Bring the system back to the role {}", self.role)?;
+        writeln!(text, "# Review the commands before running them")?;
+        if !self.problems.is_empty() {
+            writeln!(
+                text,
+                "# The role cannot be resolved, fix it before remediating:"
+            )?;
+            for problem in &self.problems {
+                writeln!(text, "#   {}", problem)?;
+            }
+        } else if self.commands.is_empty() {
+            writeln!(text, "# Nothing to do, the system matches the role")?;
+        }
+        if self.transactional {
+            writeln!(text, "# The changes are applied after a reboot")?;
+        }
+        writeln!(text, "set -e")?;
+        for command in &self.commands {
+            writeln!(text, "{}", command)?;
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        Status {
+            role: "server".to_string(),
+            removed_patterns: vec!["base".to_string()],
+            added_packages: vec!["vim".to_string(), "it's; rm -rf /".to_string()],
+            ..Status::default()
+        }
+    }
+
+    #[test]
+    fn zypper_commands() {
+        let remediation = Remediation::new(&status(), false);
+        assert_eq!(
+            remediation.commands,
+            vec![
+                "zypper --non-interactive install --type pattern 'base'",
+                r"zypper --non-interactive remove 'vim' 'it'\''s; rm -rf /'",
+            ]
+        );
+    }
+
+    #[test]
+    fn transactional_commands_continue() {
+        let remediation = Remediation::new(&status(), true);
+        assert_eq!(
+            remediation.commands,
+            vec![
+                "transactional-update -n pkg install --type pattern 'base'",
+                r"transactional-update -n --continue pkg remove 'vim' 'it'\''s; rm -rf /'",
+            ]
+        );
+    }
+}
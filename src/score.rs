@@ -0,0 +1,405 @@
+use std::cmp::Eq;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::hash::Hash;
+
+use serde::Serialize;
+
+use crate::shell::var;
+use crate::{Installation, ReferenceInstallation, Result, Roles, PROVIDES_PREFIX};
+
+// Weight of each signal in the score of a role.  A signal that the
+// role does not define is not taken into account
+const PATTERNS_WEIGHT: f64 = 0.5;
+const PACKAGES_WEIGHT: f64 = 0.25;
+const PRODUCTS_WEIGHT: f64 = 0.15;
+const OPTIONAL_WEIGHT: f64 = 0.1;
+
+/// How close the installed system is to a role.  All the values are
+/// between 0 and 1
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleScore {
+    pub role: String,
+    pub score: f64,
+    /// Jaccard index of the installed and the role patterns
+    pub patterns: Option<f64>,
+    /// Fraction of the role packages that are installed
+    pub packages: Option<f64>,
+    /// The base product is one of the role products
+    pub products: Option<f64>,
+    /// Fraction of the optional patterns and packages that are
+    /// installed
+    pub optional: Option<f64>,
+}
+
+/// Roles ordered by score, from the closer to the installed system
+#[derive(Debug, Default, Serialize)]
+pub struct RoleRanking {
+    pub scores: Vec<RoleScore>,
+    pub confidence: f64,
+}
+
+fn jaccard<T>(set1: &HashSet<T>, set2: &HashSet<T>) -> Option<f64>
+where
+    T: Eq + Hash,
+{
+    let union = set1.union(set2).count();
+    let intersection = set1.intersection(set2).count();
+    if union == 0 {
+        None
+    } else {
+        Some(intersection as f64 / union as f64)
+    }
+}
+
+/// Fraction of `expected` that is in `installed`
+fn coverage<T>(installed: &HashSet<T>, expected: &HashSet<T>) -> Option<f64>
+where
+    T: Eq + Hash,
+{
+    if expected.is_empty() {
+        None
+    } else {
+        Some(expected.intersection(installed).count() as f64 / expected.len() as f64)
+    }
+}
+
+impl RoleScore {
+    fn new(role: &str, reference: &ReferenceInstallation, installation: &Installation) -> Self {
+        let names = |packages: &[libsolv_rs::pool::Package]| -> HashSet<String> {
+            packages.iter().map(|p| p.name.clone()).collect()
+        };
+        let set = |names: &[String]| -> HashSet<String> { names.iter().cloned().collect() };
+        // The `provides:` packages are not names, they only matter
+        // when the role is resolved
+        let package_set = |names: &[String]| -> HashSet<String> {
+            names
+                .iter()
+                .filter(|p| !p.starts_with(PROVIDES_PREFIX))
+                .cloned()
+                .collect()
+        };
+
+        let installed_patterns = names(&installation.patterns);
+        let installed_packages = names(&installation.packages);
+        let installed_products = names(&installation.products);
+
+        let patterns = jaccard(&installed_patterns, &set(&reference.patterns));
+        let packages = coverage(&installed_packages, &package_set(&reference.packages));
+        let products = if reference.products.is_empty() {
+            None
+        } else if reference
+            .products
+            .iter()
+            .any(|p| installed_products.contains(p))
+        {
+            Some(1.0)
+        } else {
+            Some(0.0)
+        };
+        let installed_optional = &installed_patterns | &installed_packages;
+        let optional = coverage(
+            &installed_optional,
+            &(&set(&reference.patterns_opt) | &package_set(&reference.packages_opt)),
+        );
+
+        let mut total = 0.0;
+        let mut weights = 0.0;
+        for (signal, weight) in [
+            (patterns, PATTERNS_WEIGHT),
+            (packages, PACKAGES_WEIGHT),
+            (products, PRODUCTS_WEIGHT),
+            (optional, OPTIONAL_WEIGHT),
+        ] {
+            if let Some(signal) = signal {
+                total += signal * weight;
+                weights += weight;
+            }
+        }
+
+        RoleScore {
+            role: role.to_string(),
+            score: if weights > 0.0 { total / weights } else { 0.0 },
+            patterns,
+            packages,
+            products,
+            optional,
+        }
+    }
+}
+
+impl RoleRanking {
+    /// Score all the roles, with the values that they inherit already
+    /// resolved by `Roles::from_config`
+    pub(crate) fn new(roles: &Roles, installation: &Installation) -> Self {
+        let mut scores: Vec<_> = roles
+            .0
+            .iter()
+            .map(|(role, reference)| RoleScore::new(role, reference, installation))
+            .collect();
+        // Ties are decided by the role name, so the result does not
+        // depend on the order of the configuration
+        scores.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.role.cmp(&b.role)));
+
+        // The confidence is the advantage of the best role over the
+        // runner-up.  Close to 0 means that the detection is ambiguous
+        let confidence = match &scores[..] {
+            [] => 0.0,
+            [best] => best.score,
+            [best, runner_up, ..] => best.score - runner_up.score,
+        };
+
+        RoleRanking { scores, confidence }
+    }
+
+    /// Best role, if any role is close to the installed system
+    pub fn best(&self) -> Option<&RoleScore> {
+        self.scores.first().filter(|s| s.score > 0.0)
+    }
+
+    /// Next best roles after `role`, that can be a pinned role and not
+    /// the best one
+    pub fn runner_ups(&self, role: &str, count: usize) -> Vec<&RoleScore> {
+        self.scores
+            .iter()
+            .filter(|s| s.role != role)
+            .take(count)
+            .collect()
+    }
+
+    pub fn to_shell(&self) -> String {
+        let mut lines = vec![var("ROLE_CONFIDENCE", format!("{:.2}", self.confidence))];
+        for score in &self.scores {
+            lines.push(
+                [
+                    var("ROLE", &score.role),
+                    var("SCORE", format!("{:.2}", score.score)),
+                ]
+                .join(" "),
+            );
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let signal = |s: Option<f64>| s.map(|s| format!("{:.2}", s)).unwrap_or("-".into());
+
+        let mut text = String::new();
+        writeln!(text, "Confidence: {:.2}", self.confidence)?;
+        writeln!(
+            text,
+            "{:<20} {:>6} {:>9} {:>9} {:>9} {:>9}",
+            "ROLE", "SCORE", "PATTERNS", "PACKAGES", "PRODUCTS", "OPTIONAL"
+        )?;
+        for score in &self.scores {
+            writeln!(
+                text,
+                "{:<20} {:>6.2} {:>9} {:>9} {:>9} {:>9}",
+                score.role,
+                score.score,
+                signal(score.patterns),
+                signal(score.packages),
+                signal(score.products),
+                signal(score.optional)
+            )?;
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages(names: &[&str]) -> Vec<libsolv_rs::pool::Package> {
+        names
+            .iter()
+            .map(|name| libsolv_rs::pool::Package {
+                name: name.to_string(),
+                version: "1.0-1.1".to_string(),
+                arch: "x86_64".to_string(),
+                vendor: String::new(),
+                repo: String::new(),
+            })
+            .collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn installation(products: &[&str], patterns: &[&str], names: &[&str]) -> Installation {
+        Installation {
+            products: packages(products),
+            patterns: packages(patterns),
+            packages: packages(names),
+        }
+    }
+
+    fn roles(roles: Vec<(&str, ReferenceInstallation)>) -> Roles {
+        Roles(roles.into_iter().map(|(r, i)| (r.to_string(), i)).collect())
+    }
+
+    #[test]
+    fn jaccard_index() {
+        assert_eq!(
+            jaccard(&set(&["a", "b"]), &set(&["b", "c"])),
+            Some(1.0 / 3.0)
+        );
+        assert_eq!(jaccard(&set(&["a"]), &set(&["a"])), Some(1.0));
+        assert_eq!(jaccard(&set(&["a"]), &set(&[])), Some(0.0));
+        assert_eq!(jaccard(&set(&[]), &set(&[])), None);
+    }
+
+    #[test]
+    fn coverage_of_expected() {
+        assert_eq!(
+            coverage(&set(&["a", "b", "c"]), &set(&["a", "d"])),
+            Some(0.5)
+        );
+        assert_eq!(coverage(&set(&[]), &set(&["a"])), Some(0.0));
+        assert_eq!(coverage(&set(&["a"]), &set(&[])), None);
+    }
+
+    #[test]
+    fn weighted_signals() {
+        let installation = installation(&["SLES"], &["base"], &["vim"]);
+
+        // Only the signals defined by the role are weighted
+        let reference = ReferenceInstallation {
+            patterns: strings(&["base", "x11"]),
+            ..ReferenceInstallation::default()
+        };
+        let score = RoleScore::new("role", &reference, &installation);
+        assert_eq!(score.patterns, Some(0.5));
+        assert_eq!(score.packages, None);
+        assert_eq!(score.products, None);
+        assert_eq!(score.optional, None);
+        assert_eq!(score.score, 0.5);
+
+        let reference = ReferenceInstallation {
+            products: strings(&["SLED", "SLES"]),
+            patterns: strings(&["base"]),
+            packages: strings(&["vim", "emacs"]),
+            patterns_opt: strings(&["x11"]),
+            ..ReferenceInstallation::default()
+        };
+        let score = RoleScore::new("role", &reference, &installation);
+        assert_eq!(score.patterns, Some(1.0));
+        assert_eq!(score.packages, Some(0.5));
+        assert_eq!(score.products, Some(1.0));
+        assert_eq!(score.optional, Some(0.0));
+        let expected = (PATTERNS_WEIGHT + 0.5 * PACKAGES_WEIGHT + PRODUCTS_WEIGHT)
+            / (PATTERNS_WEIGHT + PACKAGES_WEIGHT + PRODUCTS_WEIGHT + OPTIONAL_WEIGHT);
+        assert!((score.score - expected).abs() < 1e-9);
+
+        // The provides are not names of packages
+        let reference = ReferenceInstallation {
+            packages: strings(&["vim", "provides:kernel"]),
+            ..ReferenceInstallation::default()
+        };
+        let score = RoleScore::new("role", &reference, &installation);
+        assert_eq!(score.packages, Some(1.0));
+        let reference = ReferenceInstallation {
+            packages_opt: strings(&["vim", "provides:kernel"]),
+            ..ReferenceInstallation::default()
+        };
+        let score = RoleScore::new("role", &reference, &installation);
+        assert_eq!(score.optional, Some(1.0));
+
+        let reference = ReferenceInstallation {
+            products: strings(&["SLED"]),
+            ..ReferenceInstallation::default()
+        };
+        let score = RoleScore::new("role", &reference, &installation);
+        assert_eq!(score.products, Some(0.0));
+        assert_eq!(score.score, 0.0);
+    }
+
+    #[test]
+    fn ranking_order_and_confidence() {
+        let installation = installation(&[], &["base", "x11"], &[]);
+        let ranking = RoleRanking::new(
+            &roles(vec![
+                (
+                    "server",
+                    ReferenceInstallation {
+                        patterns: strings(&["base"]),
+                        ..ReferenceInstallation::default()
+                    },
+                ),
+                (
+                    "desktop",
+                    ReferenceInstallation {
+                        patterns: strings(&["base", "x11"]),
+                        ..ReferenceInstallation::default()
+                    },
+                ),
+            ]),
+            &installation,
+        );
+        let order: Vec<_> = ranking.scores.iter().map(|s| s.role.as_str()).collect();
+        assert_eq!(order, vec!["desktop", "server"]);
+        assert_eq!(ranking.best().map(|s| s.role.as_str()), Some("desktop"));
+        assert_eq!(ranking.confidence, 0.5);
+
+        let runner_ups: Vec<_> = ranking
+            .runner_ups("desktop", 3)
+            .iter()
+            .map(|s| s.role.as_str())
+            .collect();
+        assert_eq!(runner_ups, vec!["server"]);
+        let runner_ups: Vec<_> = ranking
+            .runner_ups("server", 3)
+            .iter()
+            .map(|s| s.role.as_str())
+            .collect();
+        assert_eq!(runner_ups, vec!["desktop"]);
+        assert!(ranking.runner_ups("desktop", 0).is_empty());
+    }
+
+    #[test]
+    fn ties_decided_by_name() {
+        let reference = || ReferenceInstallation {
+            patterns: strings(&["base"]),
+            ..ReferenceInstallation::default()
+        };
+        let installation = installation(&[], &["base"], &[]);
+        let ranking = RoleRanking::new(
+            &roles(vec![
+                ("b", reference()),
+                ("c", reference()),
+                ("a", reference()),
+            ]),
+            &installation,
+        );
+        let order: Vec<_> = ranking.scores.iter().map(|s| s.role.as_str()).collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+        assert_eq!(ranking.confidence, 0.0);
+    }
+
+    #[test]
+    fn no_role_detected() {
+        let reference = ReferenceInstallation {
+            patterns: strings(&["x11"]),
+            ..ReferenceInstallation::default()
+        };
+        let installation = installation(&[], &["base"], &[]);
+        let ranking = RoleRanking::new(&roles(vec![("desktop", reference)]), &installation);
+        assert!(ranking.best().is_none());
+
+        let ranking = RoleRanking::new(&Roles::default(), &installation);
+        assert!(ranking.best().is_none());
+        assert_eq!(ranking.confidence, 0.0);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Quote the value for a shell, so it can be `source`d without
+/// expanding or running anything.  Single quotes keep everything
+/// literal, and a single quote is written as `'\''`
+pub(crate) fn quote(value: impl Display) -> String {
+    format!("'{}'", value.to_string().replace('\'', r"'\''"))
+}
+
+/// `KEY='value'` assignment
+pub(crate) fn var(key: &str, value: impl Display) -> String {
+    format!("{}={}", key, quote(value))
+}
+
+/// Read the `KEY=value` assignments written with `var`.  The values
+/// in double quotes of the older files are also understood
+pub(crate) fn parse(content: &str) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    let mut chars = content.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            // Not an assignment, skip the word
+            continue;
+        }
+
+        let mut value = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            match c {
+                '\'' => {
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                '"' => {
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' => value.extend(chars.next()),
+                            c => value.push(c),
+                        }
+                    }
+                }
+                '\\' => value.extend(chars.next()),
+                c => value.push(c),
+            }
+        }
+        vars.insert(key, value);
+    }
+
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &str) -> String {
+        parse(&var("KEY", value)).remove("KEY").unwrap()
+    }
+
+    #[test]
+    fn quote_single_quotes() {
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn round_trip_values() {
+        for value in [
+            "",
+            "plain",
+            "two words",
+            "it's",
+            "''",
+            r#"say "hi""#,
+            "$HOME ${PATH}",
+            "`reboot` $(reboot)",
+            "back\\slash",
+            "first line\nsecond line",
+            "; rm -rf /",
+            "  leading and trailing  ",
+        ] {
+            assert_eq!(round_trip(value), value);
+        }
+    }
+
+    #[test]
+    fn parse_many_assignments() {
+        let content = [
+            var("A", "one"),
+            var("B", "two words"),
+            var("C", ""),
+            var("D", "multi\nline"),
+        ]
+        .join("\n");
+        let vars = parse(&content);
+        assert_eq!(vars.len(), 4);
+        assert_eq!(vars["A"], "one");
+        assert_eq!(vars["B"], "two words");
+        assert_eq!(vars["C"], "");
+        assert_eq!(vars["D"], "multi\nline");
+
+        // Many assignments in the same line
+        let vars = parse(&[var("A", "a a"), var("B", "b")].join(" "));
+        assert_eq!(vars["A"], "a a");
+        assert_eq!(vars["B"], "b");
+    }
+
+    #[test]
+    fn parse_double_quoted() {
+        let vars = parse(
+            "ROLE=\"server\"\nADDED_PACKAGES=\"vim emacs\"\nEMPTY=\"\"\nESCAPED=\"a \\\"b\\\" \\$c\"",
+        );
+        assert_eq!(vars["ROLE"], "server");
+        assert_eq!(vars["ADDED_PACKAGES"], "vim emacs");
+        assert_eq!(vars["EMPTY"], "");
+        assert_eq!(vars["ESCAPED"], "a \"b\" $c");
+    }
+
+    #[test]
+    fn parse_skips_other_words() {
+        let vars = parse("# comment\nexport A='a'\nB=b");
+        assert_eq!(vars["A"], "a");
+        assert_eq!(vars["B"], "b");
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt::Write as _;
+use std::fs;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::shell::{self, var};
+use crate::Result;
+
+/// Status of the system, as stored in the `ostatus` file
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Status {
+    pub date: DateTime<Utc>,
+    pub product: String,
+    pub version_id: String,
+    pub role: String,
+    /// The role comes from the configuration, and is not detected
+    pub role_pinned: bool,
+    /// Score of the role, between 0 and 1
+    pub role_score: f64,
+    /// Advantage of the role over the runner-up
+    pub role_confidence: f64,
+    /// Next best roles, as `role:score`
+    pub runner_up_roles: Vec<String>,
+    pub base_manifest_digest: String,
+    pub system_manifest_digest: String,
+    pub added_patterns: Vec<String>,
+    pub removed_patterns: Vec<String>,
+    pub added_packages: Vec<String>,
+    pub removed_packages: Vec<String>,
+    /// Installed packages from a vendor different from the one
+    /// picked for the role
+    pub vendor_changed_packages: Vec<String>,
+    /// zypper is configured with `solver.allowVendorChange`
+    pub allow_vendor_change: bool,
+    /// Installed packages with a newer version in the repositories
+    pub outdated_packages: Vec<String>,
+    /// Installed packages that are not in any enabled repository
+    pub orphaned_packages: Vec<String>,
+    /// Installed packages whose exact version is not in any enabled
+    /// repository, as NEVRA.  The orphaned packages are not repeated
+    pub orphaned_versions: Vec<String>,
+    /// Patches from the updateinfo that are not applied, by category
+    pub needed_security_patches: usize,
+    pub needed_recommended_patches: usize,
+    pub needed_optional_patches: usize,
+    /// Reasons why the role cannot be resolved
+    pub problems: Vec<String>,
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>> {
+    let date =
+        NaiveDateTime::parse_from_str(date.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S%.f")?;
+    Ok(Utc.from_utc_datetime(&date))
+}
+
+fn age(date: &DateTime<Utc>) -> String {
+    let age = Utc::now().signed_duration_since(*date);
+    if age.num_days() > 0 {
+        format!("{} days ago", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{} hours ago", age.num_hours())
+    } else if age.num_minutes() > 0 {
+        format!("{} minutes ago", age.num_minutes())
+    } else {
+        "just now".to_string()
+    }
+}
+
+fn text_list(text: &mut String, title: &str, elements: &[String]) -> Result<()> {
+    if elements.is_empty() {
+        writeln!(text, "{}: none", title)?;
+    } else {
+        writeln!(text, "{} ({}):", title, elements.len())?;
+        for element in elements {
+            writeln!(text, "  {}", element)?;
+        }
+    }
+    Ok(())
+}
+
+impl Status {
+    /// Read the status file stored in `status_dir`
+    pub fn from_file(status_dir: &str) -> Result<Status> {
+        Status::from_shell(&fs::read_to_string(format!("{}/ostatus", status_dir))?)
+    }
+
+    /// Parse the `KEY='value'` lines written by `to_shell`
+    pub fn from_shell(content: &str) -> Result<Status> {
+        let mut entries = shell::parse(content);
+        let problems = (1..)
+            .map_while(|n| entries.remove(&format!("PROBLEM_{}", n)))
+            .collect();
+
+        let mut get = |key: &str| entries.remove(key).unwrap_or_default();
+        let list = |value: String| value.split_whitespace().map(|s| s.to_string()).collect();
+
+        let date = get("DATE");
+        Ok(Status {
+            date: if date.is_empty() {
+                DateTime::default()
+            } else {
+                parse_date(&date)?
+            },
+            product: get("PRODUCT"),
+            version_id: get("VERSION_ID"),
+            role: get("ROLE"),
+            role_pinned: get("ROLE_PINNED") == "yes",
+            role_score: get("ROLE_SCORE").parse().unwrap_or_default(),
+            role_confidence: get("ROLE_CONFIDENCE").parse().unwrap_or_default(),
+            runner_up_roles: list(get("RUNNER_UP_ROLES")),
+            base_manifest_digest: get("BASE_MANIFEST_DIGEST"),
+            system_manifest_digest: get("SYSTEM_MANIFEST_DIGEST"),
+            added_patterns: list(get("ADDED_PATTERNS")),
+            removed_patterns: list(get("REMOVED_PATTERNS")),
+            added_packages: list(get("ADDED_PACKAGES")),
+            removed_packages: list(get("REMOVED_PACKAGES")),
+            vendor_changed_packages: list(get("VENDOR_CHANGED_PACKAGES")),
+            allow_vendor_change: get("ALLOW_VENDOR_CHANGE") == "yes",
+            outdated_packages: list(get("OUTDATED_PACKAGES")),
+            orphaned_packages: list(get("ORPHANED_PACKAGES")),
+            orphaned_versions: list(get("ORPHANED_VERSIONS")),
+            needed_security_patches: get("NEEDED_SECURITY_PATCHES").parse().unwrap_or_default(),
+            needed_recommended_patches: get("NEEDED_RECOMMENDED_PATCHES")
+                .parse()
+                .unwrap_or_default(),
+            needed_optional_patches: get("NEEDED_OPTIONAL_PATCHES").parse().unwrap_or_default(),
+            problems,
+        })
+    }
+
+    /// Shell compatible representation, as stored in the `ostatus` file
+    pub fn to_shell(&self) -> String {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let mut lines = vec![
+            var("DATE", self.date),
+            var("PRODUCT", &self.product),
+            var("VERSION_ID", &self.version_id),
+            var("ROLE", &self.role),
+            var("ROLE_PINNED", yes_no(self.role_pinned)),
+            var("ROLE_SCORE", format!("{:.2}", self.role_score)),
+            var("ROLE_CONFIDENCE", format!("{:.2}", self.role_confidence)),
+            var("RUNNER_UP_ROLES", self.runner_up_roles.join(" ")),
+            var("BASE_MANIFEST_DIGEST", &self.base_manifest_digest),
+            var("SYSTEM_MANIFEST_DIGEST", &self.system_manifest_digest),
+            var("ADDED_PATTERNS", self.added_patterns.join(" ")),
+            var("REMOVED_PATTERNS", self.removed_patterns.join(" ")),
+            var("ADDED_PACKAGES", self.added_packages.join(" ")),
+            var("REMOVED_PACKAGES", self.removed_packages.join(" ")),
+            var(
+                "VENDOR_CHANGED_PACKAGES",
+                self.vendor_changed_packages.join(" "),
+            ),
+            var("ALLOW_VENDOR_CHANGE", yes_no(self.allow_vendor_change)),
+            var("OUTDATED_PACKAGES", self.outdated_packages.join(" ")),
+            var("ORPHANED_PACKAGES", self.orphaned_packages.join(" ")),
+            var("ORPHANED_VERSIONS", self.orphaned_versions.join(" ")),
+            var("NEEDED_SECURITY_PATCHES", self.needed_security_patches),
+            var(
+                "NEEDED_RECOMMENDED_PATCHES",
+                self.needed_recommended_patches,
+            ),
+            var("NEEDED_OPTIONAL_PATCHES", self.needed_optional_patches),
+        ];
+        // One variable for each problem, as the solver text can have
+        // any separator
+        for (n, problem) in self.problems.iter().enumerate() {
+            lines.push(var(&format!("PROBLEM_{}", n + 1), problem));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Human readable report
+    pub fn to_text(&self) -> Result<String> {
+        let mut text = String::new();
+
+        writeln!(text, "Product: {} {}", self.product, self.version_id)?;
+        if self.role_pinned {
+            writeln!(
+                text,
+                "Role: {} (pinned, score {:.2})",
+                self.role, self.role_score
+            )?;
+        } else {
+            writeln!(
+                text,
+                "Role: {} (score {:.2}, confidence {:.2})",
+                self.role, self.role_score, self.role_confidence
+            )?;
+        }
+        if !self.runner_up_roles.is_empty() {
+            writeln!(text, "Runner-up roles: {}", self.runner_up_roles.join(" "))?;
+        }
+        writeln!(text, "Date: {} ({})", self.date, age(&self.date))?;
+        writeln!(text)?;
+        if !self.problems.is_empty() {
+            writeln!(text, "Role {} cannot be resolved because:", self.role)?;
+            for problem in &self.problems {
+                writeln!(text, "  {}", problem)?;
+            }
+            writeln!(text)?;
+        }
+        text_list(&mut text, "Added patterns", &self.added_patterns)?;
+        text_list(&mut text, "Removed patterns", &self.removed_patterns)?;
+        text_list(&mut text, "Added packages", &self.added_packages)?;
+        text_list(&mut text, "Removed packages", &self.removed_packages)?;
+        text_list(
+            &mut text,
+            "Vendor changed packages",
+            &self.vendor_changed_packages,
+        )?;
+        writeln!(
+            text,
+            "Vendor changes allowed: {}",
+            if self.allow_vendor_change {
+                "yes"
+            } else {
+                "no"
+            }
+        )?;
+        text_list(&mut text, "Outdated packages", &self.outdated_packages)?;
+        text_list(&mut text, "Orphaned packages", &self.orphaned_packages)?;
+        text_list(&mut text, "Orphaned versions", &self.orphaned_versions)?;
+        writeln!(
+            text,
+            "Needed patches: {} security, {} recommended, {} optional",
+            self.needed_security_patches,
+            self.needed_recommended_patches,
+            self.needed_optional_patches
+        )?;
+        writeln!(text)?;
+        writeln!(text, "Base manifest digest: {}", self.base_manifest_digest)?;
+        writeln!(
+            text,
+            "System manifest digest: {}",
+            self.system_manifest_digest
+        )?;
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problems_round_trip() {
+        let status = Status {
+            role: "server".to_string(),
+            problems: vec![
+                "nothing provides 'foo'; needed by bar".to_string(),
+                "conflicts with \"baz\"\nand more".to_string(),
+            ],
+            ..Status::default()
+        };
+        let parsed = Status::from_shell(&status.to_shell()).unwrap();
+        assert_eq!(parsed.role, "server");
+        assert_eq!(parsed.problems, status.problems);
+
+        let parsed = Status::from_shell(&Status::default().to_shell()).unwrap();
+        assert!(parsed.problems.is_empty());
+    }
+}